@@ -1,6 +1,4 @@
-use crate::base::RouteableComponent;
-
-use crate::route::longpull::LongPollRoute;
+use crate::base::{RouteableComponent, RouteError};
 
 use std::sync::Arc;
 
@@ -9,15 +7,33 @@ use tokio::sync::oneshot::Sender;
 use serde_json::Value;
 
 
-pub enum AddRouteType {
-    Longpull(Arc<LongPollRoute>),
-    Webhook(Arc<dyn RouteableComponent>),
-}
-
 pub enum ApiMessage {
     AddRoute {
-        route: AddRouteType,
-        sublevel: i8
+        parent: Vec<usize>,
+        index: Option<usize>,
+        weight: Option<u32>,
+        route: Arc<dyn RouteableComponent>,
+        response: Sender<Result<(), RouteError>>,
+    },
+    RemoveRoute {
+        path: Vec<usize>,
+        response: Sender<Result<(), RouteError>>,
     },
-    GetRoutes(Sender<Value>)
-}
\ No newline at end of file
+    UpdateRoute {
+        path: Vec<usize>,
+        route: Option<Arc<dyn RouteableComponent>>,
+        parent: Option<Vec<usize>>,
+        index: Option<usize>,
+        weight: Option<u32>,
+        response: Sender<Result<(), RouteError>>,
+    },
+    GetRoutes(Sender<Value>),
+    SetWeight {
+        path: Vec<usize>,
+        weight: u32,
+        response: Sender<Result<(), RouteError>>,
+    },
+    GetStats(Sender<Value>),
+    Redrive(Sender<Value>),
+    GetUpdates(Sender<Value>),
+}
@@ -1,40 +1,80 @@
 
-use axum::{http, extract::State, Json, response::IntoResponse};
+use axum::{http, extract::State, Json, response::{IntoResponse, Response}};
 use serde_json::Value;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 use tokio;
 use std::sync::Arc;
 
-use crate::api::schemas::{AddRoute, RouteType};
-use crate::api::message::{ApiMessage, AddRouteType};
+use crate::api::schemas::{AddRoute, RemoveRoute, UpdateRoute, SetWeight};
+use crate::api::message::ApiMessage;
+use crate::base::{RouteableComponent, RouteError};
+use crate::config::schema::RouteConfig;
+use crate::config::setup::RouteBuilder;
 
-use crate::route::webhook::WebhookRoute;
-use crate::route::longpull::LongPollRoute;
 
+fn route_status(result: Result<Result<(), RouteError>, oneshot::error::RecvError>) -> http::StatusCode {
+    match result {
+        Ok(Ok(())) => http::StatusCode::OK,
+        Ok(Err(RouteError::NotFound)) => http::StatusCode::NOT_FOUND,
+        Ok(Err(RouteError::Unsupported)) => http::StatusCode::BAD_REQUEST,
+        Ok(Err(RouteError::Conflict)) => http::StatusCode::CONFLICT,
+        Err(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn build_route(cfg: RouteConfig) -> Result<Arc<dyn RouteableComponent>, String> {
+    RouteBuilder::default().build(cfg)
+}
+
+
+
+pub async fn add_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<AddRoute>) -> Response {
+    let route = match build_route(data.route.into_config()) {
+        Ok(route) => route,
+        Err(err) => return (http::StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let (tx_response, rx_response) = oneshot::channel();
 
+    let _ = tx.send(ApiMessage::AddRoute {
+        parent: data.parent,
+        index: data.index,
+        weight: data.weight,
+        route,
+        response: tx_response,
+    }).await;
 
+    route_status(rx_response.await).into_response()
+}
 
+pub async fn remove_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<RemoveRoute>) -> http::StatusCode {
+    let (tx_response, rx_response) = oneshot::channel();
 
+    let _ = tx.send(ApiMessage::RemoveRoute {
+        path: data.path,
+        response: tx_response,
+    }).await;
 
+    route_status(rx_response.await)
+}
 
-pub async fn add_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<AddRoute>)  {
-    let route = match data.typee {
-        RouteType::Longpull(route) => {
-            let update = LongPollRoute::new(route.path);
-            AddRouteType::Longpull(Arc::new(update))
-        },
-        RouteType::Webhook(route) => {
-            let update = WebhookRoute::new(route.url);
-            AddRouteType::Webhook(Arc::new(update))
-        }
+pub async fn update_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<UpdateRoute>) -> Response {
+    let route = match data.route.map(build_route).transpose() {
+        Ok(route) => route,
+        Err(err) => return (http::StatusCode::BAD_REQUEST, err).into_response(),
     };
+    let (tx_response, rx_response) = oneshot::channel();
 
-    let _ = tx.send(ApiMessage::AddRoute{
-        sublevel: data.sublevel,
-        route
-        }).await;
+    let _ = tx.send(ApiMessage::UpdateRoute {
+        path: data.path,
+        route,
+        parent: data.parent,
+        index: data.index,
+        weight: data.weight,
+        response: tx_response,
+    }).await;
 
+    route_status(rx_response.await).into_response()
 }
 
 
@@ -50,4 +90,86 @@ pub async fn get_routes(State(tx): State<Sender<ApiMessage>>) -> Result<Json<Val
             http::StatusCode::INTERNAL_SERVER_ERROR
         ),
     }
-}
\ No newline at end of file
+}
+
+
+
+pub async fn set_weight(State(tx): State<Sender<ApiMessage>>, Json(data): Json<SetWeight>) -> http::StatusCode {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::SetWeight {
+        path: data.path,
+        weight: data.weight,
+        response: tx_response,
+    }).await;
+
+    route_status(rx_response.await)
+}
+
+
+
+pub async fn get_stats(State(tx): State<Sender<ApiMessage>>) -> Result<Json<Value>, impl IntoResponse> {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetStats(tx_response)).await;
+
+    match rx_response.await {
+        Ok(json) => Ok(Json::from(json)),
+        Err(_) => Err(
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        ),
+    }
+}
+
+pub async fn redrive(State(tx): State<Sender<ApiMessage>>) -> Result<Json<Value>, impl IntoResponse> {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::Redrive(tx_response)).await;
+
+    match rx_response.await {
+        Ok(json) => Ok(Json::from(json)),
+        Err(_) => Err(
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        ),
+    }
+}
+
+pub async fn get_updates(State(tx): State<Sender<ApiMessage>>) -> Result<Json<Value>, impl IntoResponse> {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetUpdates(tx_response)).await;
+
+    match rx_response.await {
+        Ok(json) => Ok(Json::from(json)),
+        Err(_) => Err(
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        ),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_route_that_fails_to_build_is_a_bad_request() {
+        // the write-ahead log cannot be opened, its dir is a file
+        let file = std::env::temp_dir().join(format!("tgin-api-wal-{}", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let data: AddRoute = serde_json::from_value(json!({
+            "route": {"LongPollRoute": {"path": "/api/broken", "wal": {"dir": file}}},
+        })).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let response = add_route(State(tx), Json(data)).await;
+
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("Failed to open write-ahead log"));
+        assert!(rx.try_recv().is_err());
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 pub mod router;
 mod schemas;
 pub mod message;
-pub mod methods;
\ No newline at end of file
+pub mod methods;
+pub mod tree;
\ No newline at end of file
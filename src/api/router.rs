@@ -1,4 +1,4 @@
-use axum::{Router, routing::{post, get}};
+use axum::{Router, routing::{post, get, patch}};
 use serde_json::{Value};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Sender, Receiver};
@@ -42,7 +42,11 @@ impl Serverable for Api {
     async fn set_server(&self, main_router: Router<Sender<Value>>) -> Router<Sender<Value>> {
         let router = Router::new()
             .route("/routes", get(methods::get_routes))
-            .route("/route", post(methods::add_route))
+            .route("/route", post(methods::add_route).patch(methods::update_route).delete(methods::remove_route))
+            .route("/route/weight", patch(methods::set_weight))
+            .route("/stats", get(methods::get_stats))
+            .route("/dead-letters/redrive", post(methods::redrive))
+            .route("/updates", get(methods::get_updates))
             .with_state(self.tx.clone());
 
 
@@ -1,8 +1,6 @@
 use serde::Deserialize;
 
-fn default_sublevel() -> i8 {
-    0
-}
+use crate::config::schema::{RouteConfig, default_webhook_timeout};
 
 
 #[derive(Deserialize, Debug)]
@@ -25,10 +23,95 @@ pub enum RouteType {
     Longpull(AddLongpullRoute),
 }
 
+/// A single route given by `type`, or a route or whole subtree in the format of `tgin.ron`.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum NewRoute {
+    Config { route: RouteConfig },
+    Short(RouteType),
+}
+
+impl NewRoute {
+    pub fn into_config(self) -> RouteConfig {
+        match self {
+            NewRoute::Config { route } => route,
+            NewRoute::Short(RouteType::Webhook(route)) => RouteConfig::WebhookRoute {
+                url: route.url,
+                timeout: default_webhook_timeout(),
+                retry: None,
+                dead_letter: None,
+                circuit_breaker: None,
+                health_check: None,
+            },
+            NewRoute::Short(RouteType::Longpull(route)) => RouteConfig::LongPollRoute {
+                path: route.path,
+                max_poll_age: None,
+                max_len: None,
+                max_bytes: None,
+                overflow: Default::default(),
+                wal: None,
+            },
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct AddRoute {
     #[serde(flatten)]
-    pub typee: RouteType,
-    #[serde(default = "default_sublevel")]
-    pub sublevel: i8
-}
\ No newline at end of file
+    pub route: NewRoute,
+    #[serde(default)]
+    pub parent: Vec<usize>,
+    #[serde(default)]
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub weight: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RemoveRoute {
+    pub path: Vec<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateRoute {
+    pub path: Vec<usize>,
+    #[serde(default)]
+    pub route: Option<RouteConfig>,
+    #[serde(default)]
+    pub parent: Option<Vec<usize>>,
+    #[serde(default)]
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub weight: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SetWeight {
+    pub path: Vec<usize>,
+    pub weight: u32,
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_short_and_config_routes() {
+        let short: AddRoute = serde_json::from_value(json!({"type": "Webhook", "url": "http://bot:9000", "parent": [1]})).unwrap();
+        assert_eq!(short.parent, vec![1]);
+        assert!(matches!(short.route.into_config(), RouteConfig::WebhookRoute { url, .. } if url == "http://bot:9000"));
+
+        let subtree: AddRoute = serde_json::from_value(json!({
+            "route": {"RoundRobinLB": {"routes": [{"LongPollRoute": {"path": "/bot/a"}}]}},
+            "index": 0,
+            "weight": 3,
+        })).unwrap();
+        assert_eq!((subtree.index, subtree.weight), (Some(0), Some(3)));
+        assert!(matches!(subtree.route.into_config(), RouteConfig::RoundRobinLB { routes } if routes.len() == 1));
+
+        assert!(serde_json::from_value::<AddRoute>(json!({"type": "Carrier"})).is_err());
+    }
+}
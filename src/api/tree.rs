@@ -0,0 +1,256 @@
+use crate::base::{RouteableComponent, RouteError, route_at, descendants};
+
+use std::sync::Arc;
+
+
+/// Paths polled by consumers anywhere in the tree.
+pub async fn poll_paths(route: &Arc<dyn RouteableComponent>) -> Vec<String> {
+    descendants(route).await.iter()
+        .filter_map(|node| node.poll_path().map(str::to_string))
+        .collect()
+}
+
+/// Two long-poll routes in one tree cannot share a path. `replaced` is about to leave the tree.
+async fn check_paths(
+    root: &Arc<dyn RouteableComponent>,
+    route: &Arc<dyn RouteableComponent>,
+    replaced: Option<&Arc<dyn RouteableComponent>>,
+) -> Result<(), RouteError> {
+    let mut taken = poll_paths(root).await;
+    if let Some(replaced) = replaced {
+        for path in poll_paths(replaced).await {
+            if let Some(i) = taken.iter().position(|taken| *taken == path) {
+                taken.remove(i);
+            }
+        }
+    }
+    for path in poll_paths(route).await {
+        if taken.contains(&path) {
+            return Err(RouteError::Conflict);
+        }
+        taken.push(path);
+    }
+    Ok(())
+}
+
+/// Puts a route taken out of `parent` back where it was.
+async fn put_back(parent: &Arc<dyn RouteableComponent>, index: usize, route: Arc<dyn RouteableComponent>, weight: Option<u32>) {
+    if parent.insert_route(Some(index), route).await.is_ok() {
+        if let Some(weight) = weight {
+            let _ = parent.set_weight(index, weight).await;
+        }
+    }
+}
+
+/// Inserts `route` as child `index` of the load balancer at `parent`, or appends it.
+/// `weight` is only accepted by a `WeightedRoundRobinLB`, other children start with weight 1.
+pub async fn insert(
+    root: &Arc<dyn RouteableComponent>,
+    parent: &[usize],
+    index: Option<usize>,
+    weight: Option<u32>,
+    route: Arc<dyn RouteableComponent>,
+) -> Result<(), RouteError> {
+    let parent = route_at(root, parent).await.ok_or(RouteError::NotFound)?;
+    check_paths(root, &route, None).await?;
+
+    let index = parent.insert_route(index, route).await?;
+    if let Some(weight) = weight {
+        if let Err(err) = parent.set_weight(index, weight).await {
+            let _ = parent.remove_route(index).await;
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Takes the route at `path` out of the tree and returns it. The root cannot be removed.
+pub async fn remove(root: &Arc<dyn RouteableComponent>, path: &[usize]) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+    let (index, parent) = path.split_last().ok_or(RouteError::Unsupported)?;
+    let parent = route_at(root, parent).await.ok_or(RouteError::NotFound)?;
+    parent.remove_route(*index).await
+}
+
+/// Replaces the route at `path` with `route` and/or moves it under the load balancer at `parent`.
+///
+/// Paths refer to the tree before the change, `index` to the children of the target after the
+/// route was taken out. Without `parent` the route stays at its place unless `index` moves it.
+/// A route keeps its weight when it stays in or moves into a `WeightedRoundRobinLB`. Nothing
+/// changes when any step fails. Returns the replaced route.
+pub async fn update(
+    root: &mut Arc<dyn RouteableComponent>,
+    path: &[usize],
+    route: Option<Arc<dyn RouteableComponent>>,
+    parent: Option<&[usize]>,
+    index: Option<usize>,
+    weight: Option<u32>,
+) -> Result<Option<Arc<dyn RouteableComponent>>, RouteError> {
+    let Some((at, from)) = path.split_last() else {
+        // The root can only be swapped as a whole.
+        return match (route, parent, index, weight) {
+            (Some(route), None, None, None) => Ok(Some(std::mem::replace(root, route))),
+            _ => Err(RouteError::Unsupported),
+        };
+    };
+
+    let from = route_at(root, from).await.ok_or(RouteError::NotFound)?;
+    let current = from.child(*at).await.ok_or(RouteError::NotFound)?;
+    let to = match parent {
+        Some(parent) if parent.starts_with(path) => return Err(RouteError::Unsupported),
+        Some(parent) => route_at(root, parent).await.ok_or(RouteError::NotFound)?,
+        None => from.clone(),
+    };
+    if let Some(route) = &route {
+        check_paths(root, route, Some(&current)).await?;
+    }
+
+    let previous_weight = from.weight(*at).await;
+    let index = index.or(if parent.is_none() { Some(*at) } else { None });
+
+    let old = from.remove_route(*at).await?;
+    let new = route.clone().unwrap_or_else(|| old.clone());
+
+    let placed = match to.insert_route(index, new).await {
+        Ok(placed) => placed,
+        Err(err) => {
+            put_back(&from, *at, old, previous_weight).await;
+            return Err(err);
+        }
+    };
+
+    let weighted = match (weight, previous_weight) {
+        (Some(weight), _) => to.set_weight(placed, weight).await,
+        (None, Some(weight)) => {
+            let _ = to.set_weight(placed, weight).await;
+            Ok(())
+        }
+        (None, None) => Ok(()),
+    };
+    if let Err(err) = weighted {
+        let _ = to.remove_route(placed).await;
+        put_back(&from, *at, old, previous_weight).await;
+        return Err(err);
+    }
+
+    Ok(route.map(|_| old))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lb::roundrobin::RoundRobinLB;
+    use crate::lb::weighted::WeightedRoundRobinLB;
+    use crate::route::longpull::LongPollRoute;
+    use crate::router::updatetype::UpdateTypeRouter;
+    use crate::mock::routes::MockCallsRoute;
+    use serde_json::Value;
+
+    fn mock(id: &str) -> Arc<dyn RouteableComponent> {
+        Arc::new(MockCallsRoute::new(id))
+    }
+
+    // root: RoundRobin[ a, Weighted[ b(2), c(5) ] ]
+    fn tree() -> Arc<dyn RouteableComponent> {
+        Arc::new(RoundRobinLB::new(vec![
+            mock("a"),
+            Arc::new(WeightedRoundRobinLB::new(vec![(mock("b"), 2), (mock("c"), 5)])),
+        ]))
+    }
+
+    async fn ids(root: &Arc<dyn RouteableComponent>) -> Value {
+        root.json_struct().await
+    }
+
+    #[tokio::test]
+    async fn test_insert_under_nested_balancer() {
+        let root = tree();
+
+        insert(&root, &[1], Some(0), Some(7), mock("d")).await.unwrap();
+        let json = ids(&root).await;
+        assert_eq!(json["routes"][1]["routes"][0]["route"]["id"], "d");
+        assert_eq!(json["routes"][1]["routes"][0]["weight"], 7);
+
+        // a whole sub-balancer
+        insert(&root, &[], None, None, Arc::new(RoundRobinLB::new(vec![mock("e")]))).await.unwrap();
+        assert_eq!(ids(&root).await["routes"][2]["routes"][0]["id"], "e");
+
+        assert_eq!(insert(&root, &[0], None, None, mock("x")).await, Err(RouteError::Unsupported));
+        assert_eq!(insert(&root, &[5], None, None, mock("x")).await, Err(RouteError::NotFound));
+        assert_eq!(insert(&root, &[], Some(9), None, mock("x")).await, Err(RouteError::NotFound));
+        // weight on a balancer without weights leaves the tree as it was
+        assert_eq!(insert(&root, &[], None, Some(2), mock("x")).await, Err(RouteError::Unsupported));
+        assert_eq!(ids(&root).await["routes"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let root = tree();
+
+        let removed = remove(&root, &[1, 0]).await.unwrap();
+        assert_eq!(removed.json_struct().await["id"], "b");
+        assert_eq!(ids(&root).await["routes"][1]["routes"][0]["route"]["id"], "c");
+
+        assert_eq!(remove(&root, &[]).await.err(), Some(RouteError::Unsupported));
+        assert_eq!(remove(&root, &[1, 5]).await.err(), Some(RouteError::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_move_between_balancers_keeps_weight() {
+        let mut root = tree();
+
+        // c moves out of the weighted balancer, then back in front with its weight
+        update(&mut root, &[1, 1], None, Some(&[]), Some(0), None).await.unwrap();
+        let json = ids(&root).await;
+        assert_eq!(json["routes"][0]["id"], "c");
+        assert_eq!(json["routes"][2]["routes"].as_array().unwrap().len(), 1);
+
+        update(&mut root, &[0], None, Some(&[2]), Some(0), Some(5)).await.unwrap();
+        let json = ids(&root).await;
+        assert_eq!(json["routes"][1]["routes"][0]["route"]["id"], "c");
+        assert_eq!(json["routes"][1]["routes"][0]["weight"], 5);
+
+        // moving within the same balancer keeps the weight
+        update(&mut root, &[1, 0], None, None, Some(1), None).await.unwrap();
+        let json = ids(&root).await;
+        assert_eq!(json["routes"][1]["routes"][1]["route"]["id"], "c");
+        assert_eq!(json["routes"][1]["routes"][1]["weight"], 5);
+
+        // not into itself
+        assert_eq!(update(&mut root, &[1], None, Some(&[1]), None, None).await.err(), Some(RouteError::Unsupported));
+    }
+
+    #[tokio::test]
+    async fn test_failed_move_puts_route_back() {
+        let mut root: Arc<dyn RouteableComponent> = Arc::new(RoundRobinLB::new(vec![
+            Arc::new(WeightedRoundRobinLB::new(vec![(mock("a"), 3)])),
+            Arc::new(UpdateTypeRouter::new(vec![], Some(mock("b")))),
+        ]));
+        let before = ids(&root).await;
+
+        // routers do not take new children
+        assert_eq!(update(&mut root, &[0, 0], None, Some(&[1]), None, None).await.err(), Some(RouteError::Unsupported));
+        assert_eq!(ids(&root).await, before);
+    }
+
+    #[tokio::test]
+    async fn test_replace_keeps_place_and_checks_paths() {
+        let mut root: Arc<dyn RouteableComponent> = Arc::new(RoundRobinLB::new(vec![
+            Arc::new(LongPollRoute::new("/tree/a".to_string())),
+            Arc::new(LongPollRoute::new("/tree/b".to_string())),
+        ]));
+
+        // a route may be replaced by one with its own path, not with the path of another one
+        let replaced = update(&mut root, &[0], Some(Arc::new(LongPollRoute::new("/tree/a".to_string()))), None, None, None).await;
+        assert!(replaced.unwrap().is_some());
+        let taken = update(&mut root, &[0], Some(Arc::new(LongPollRoute::new("/tree/b".to_string()))), None, None, None).await;
+        assert_eq!(taken.err(), Some(RouteError::Conflict));
+        let duplicate = insert(&root, &[], None, None, Arc::new(LongPollRoute::new("/tree/b".to_string()))).await;
+        assert_eq!(duplicate, Err(RouteError::Conflict));
+
+        let old = update(&mut root, &[], Some(mock("new root")), None, None, None).await.unwrap().unwrap();
+        assert_eq!(poll_paths(&old).await, vec!["/tree/a", "/tree/b"]);
+        assert_eq!(ids(&root).await["id"], "new root");
+    }
+}
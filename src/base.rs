@@ -4,21 +4,126 @@ use serde_json::{Value, json};
 
 use tokio::sync::mpsc::Sender;
 
+use std::fmt;
+use std::sync::Arc;
+
 use axum::Router;
 
 use crate::update::base::Updater;
 
-use crate::api::message::AddRouteType;
+/// Outcome of handing an update to a route.
+///
+/// `Rejected` means tgin itself refused the update before any backend saw it,
+/// so a parent balancer may safely offer it to another child. `Ignored` means a router has no
+/// route for the update on purpose: nothing failed, so it is neither retried nor dead-lettered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryResult {
+    Delivered,
+    Ignored(String),
+    Rejected(String),
+    BackendError(String),
+    Timeout(String),
+}
+
+impl DeliveryResult {
+    pub fn is_delivered(&self) -> bool {
+        matches!(self, DeliveryResult::Delivered)
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, DeliveryResult::Rejected(_))
+    }
+}
+
+impl fmt::Display for DeliveryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryResult::Delivered => write!(f, "delivered"),
+            DeliveryResult::Ignored(reason) => write!(f, "ignored: {}", reason),
+            DeliveryResult::Rejected(reason) => write!(f, "rejected: {}", reason),
+            DeliveryResult::BackendError(reason) => write!(f, "backend error: {}", reason),
+            DeliveryResult::Timeout(reason) => write!(f, "timeout: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteError {
+    Unsupported,
+    NotFound,
+    Conflict,
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Unsupported => write!(f, "operation is not supported by this route"),
+            RouteError::NotFound => write!(f, "route not found"),
+            RouteError::Conflict => write!(f, "long-poll path is already in use"),
+        }
+    }
+}
 
 
 #[async_trait]
 pub trait Routeable: Send + Sync {
-    async fn process(&self, update: Value);
+    async fn process(&self, update: Value) -> DeliveryResult;
+
+    /// Inserts a child at `index`, or appends it when `index` is `None`, and returns where it went.
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let _ = (index, route);
+        Err(RouteError::Unsupported)
+    }
+
+    /// Takes the child at `index` out of the route.
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let _ = index;
+        Err(RouteError::Unsupported)
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let _ = index;
+        None
+    }
 
-    async fn add_route(&self, route: AddRouteType) -> Result<(), ()>{
-        drop(route);
-        Err(())
+    async fn set_weight(&self, index: usize, weight: u32) -> Result<(), RouteError> {
+        let _ = (index, weight);
+        Err(RouteError::Unsupported)
     }
+
+    async fn weight(&self, index: usize) -> Option<u32> {
+        let _ = index;
+        None
+    }
+
+    async fn pending(&self) -> usize {
+        0
+    }
+
+    /// Whether the route currently accepts updates. Load balancers skip unavailable children.
+    async fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Starts the background health checks of this route itself; children are started by the caller.
+    async fn start_health_checks(&self) {}
+
+    async fn redrive(&self) -> Vec<DeliveryResult> {
+        Vec::new()
+    }
+
+    /// Path consumers poll this route at, for long-poll routes.
+    fn poll_path(&self) -> Option<&str> {
+        None
+    }
+
+    /// Stable name of a leaf route, its url or path. `HashLB` places its children on the ring by it.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Makes the route reachable through the dynamic handler, for routes added at runtime.
+    fn register(&self) {}
 }
 #[async_trait]
 pub trait Serverable {
@@ -43,3 +148,26 @@ impl<T: Updater + Serverable + Printable> UpdaterComponent for T {}
 pub trait RouteableComponent: Routeable + Serverable + Printable + Send + Sync{}
 impl<T: Routeable + Serverable + Printable> RouteableComponent for T {}
 
+
+
+pub async fn descendants(root: &Arc<dyn RouteableComponent>) -> Vec<Arc<dyn RouteableComponent>> {
+    let mut routes = vec![root.clone()];
+    let mut i = 0;
+    while i < routes.len() {
+        let mut index = 0;
+        while let Some(child) = routes[i].child(index).await {
+            routes.push(child);
+            index += 1;
+        }
+        i += 1;
+    }
+    routes
+}
+
+pub async fn route_at(root: &Arc<dyn RouteableComponent>, path: &[usize]) -> Option<Arc<dyn RouteableComponent>> {
+    let mut route = root.clone();
+    for index in path {
+        route = route.child(*index).await?;
+    }
+    Some(route)
+}
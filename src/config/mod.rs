@@ -1,2 +1,3 @@
 pub mod schema;
-pub mod setup;
\ No newline at end of file
+pub mod setup;
+pub mod reload;
\ No newline at end of file
@@ -0,0 +1,375 @@
+use crate::base::{RouteableComponent, UpdaterComponent};
+use crate::config::schema::{TginConfig, UpdateConfig, SslConfig, ApiConfig, DedupConfig, UpdateKeyConfig, ShutdownConfig};
+use crate::config::setup::{try_load_config, build_updates, check_longpoll_bots, RouteBuilder};
+
+use std::sync::Arc;
+
+
+/// What becomes of a provider after a reload.
+pub enum ProviderChange {
+    /// The running provider with this index stays as it is.
+    Keep(usize),
+    Start(Box<dyn UpdaterComponent>),
+}
+
+pub struct Reload {
+    pub route: Arc<dyn RouteableComponent>,
+    /// Providers in their new order. Running providers that are not kept are stopped.
+    pub updates: Vec<ProviderChange>,
+}
+
+/// Top-level settings, which are only read at startup.
+#[derive(Clone, PartialEq)]
+pub struct StartupSettings {
+    dark_threads: usize,
+    server_port: Option<u16>,
+    ssl: Option<SslConfig>,
+    api: Option<ApiConfig>,
+    dedup: Option<DedupConfig>,
+    ordered_by: Option<UpdateKeyConfig>,
+    shutdown: ShutdownConfig,
+    max_in_flight: usize,
+}
+
+impl StartupSettings {
+    pub fn of(conf: &TginConfig) -> Self {
+        // Spelled out, so a new top-level field does not compile until it is sorted in here.
+        let TginConfig {
+            dark_threads, server_port, ssl, updates: _, route: _, api, dedup, ordered_by, shutdown, max_in_flight,
+        } = conf;
+        Self {
+            dark_threads: *dark_threads,
+            server_port: *server_port,
+            ssl: ssl.clone(),
+            api: api.clone(),
+            dedup: dedup.clone(),
+            ordered_by: ordered_by.clone(),
+            shutdown: shutdown.clone(),
+            max_in_flight: *max_in_flight,
+        }
+    }
+
+    /// Names of the settings that differ from `other`.
+    fn changed(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("dark_threads", self.dark_threads != other.dark_threads),
+            ("server_port", self.server_port != other.server_port),
+            ("ssl", self.ssl != other.ssl),
+            ("api", self.api != other.api),
+            ("dedup", self.dedup != other.dedup),
+            ("ordered_by", self.ordered_by != other.ordered_by),
+            ("shutdown", self.shutdown != other.shutdown),
+            ("max_in_flight", self.max_in_flight != other.max_in_flight),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// Re-reads the config file and works out what changed against the running config.
+///
+/// The route tree is rebuilt with the [`RouteBuilder`] of the running one. Long-poll providers
+/// with an unchanged config keep running, changed ones are restarted. Webhook providers are bound
+/// to the server at startup, so a config that changes them is refused. The other top-level
+/// settings are left alone.
+pub struct Reloader {
+    path: String,
+    settings: StartupSettings,
+    updates: Vec<UpdateConfig>,
+    routes: RouteBuilder,
+    #[cfg(unix)]
+    hangup: Option<tokio::signal::unix::Signal>,
+}
+
+fn is_webhook(cfg: &UpdateConfig) -> bool {
+    matches!(cfg, UpdateConfig::WebhookUpdate { .. })
+}
+
+impl Reloader {
+    pub fn new(path: String, settings: StartupSettings, updates: Vec<UpdateConfig>, routes: RouteBuilder) -> Self {
+        Self {
+            path,
+            settings,
+            updates,
+            routes,
+            #[cfg(unix)]
+            hangup: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Resolves on SIGHUP. Without unix signals a reload is never requested.
+    pub async fn requested(&mut self) {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            // Installed on first use, the handler needs a running runtime.
+            let hangup = self.hangup.get_or_insert_with(|| {
+                signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler")
+            });
+            hangup.recv().await;
+        }
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await
+    }
+
+    /// Nothing changes when the new config cannot be read or built.
+    pub fn reload(&mut self) -> Result<Reload, String> {
+        let conf = try_load_config(&self.path)?;
+
+        for name in self.settings.changed(&StartupSettings::of(&conf)) {
+            eprintln!("Changes to {} take effect after a restart", name);
+        }
+
+        let (webhooks, longpolls): (Vec<UpdateConfig>, Vec<UpdateConfig>) = conf.updates.into_iter().partition(is_webhook);
+        let running_webhooks: Vec<&UpdateConfig> = self.updates.iter().filter(|cfg| is_webhook(cfg)).collect();
+        // Their endpoints are mounted on the running server, a reload cannot swap them.
+        if webhooks.iter().collect::<Vec<_>>() != running_webhooks {
+            return Err("WebhookUpdate providers changed, restart tgin to apply the new config".to_string());
+        }
+
+        let mut kept = vec![false; self.updates.len()];
+        let mut changes = Vec::new();
+        let mut configs = Vec::new();
+
+        for (i, cfg) in self.updates.iter().enumerate() {
+            if is_webhook(cfg) {
+                kept[i] = true;
+                changes.push(ProviderChange::Keep(i));
+                configs.push(cfg.clone());
+            }
+        }
+
+        for cfg in longpolls {
+            let running = self.updates.iter().enumerate()
+                .position(|(i, running)| !kept[i] && *running == cfg);
+            match running {
+                Some(i) => {
+                    kept[i] = true;
+                    changes.push(ProviderChange::Keep(i));
+                }
+                None => {
+                    let provider = build_updates(vec![cfg.clone()], self.settings.ssl.as_ref())?.pop()
+                        .expect("One config builds one provider");
+                    changes.push(ProviderChange::Start(provider));
+                }
+            }
+            configs.push(cfg);
+        }
+
+        let mut routes = self.routes.clone();
+        let route = routes.build(conf.route)?;
+        check_longpoll_bots(&configs, &routes)?;
+        self.routes = routes;
+        self.updates = configs;
+        Ok(Reload { route, updates: changes })
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::descendants;
+    use crate::config::setup::load_config;
+    use serde_json::json;
+
+    use std::path::{Path, PathBuf};
+
+    fn write_config(name: &str, updates: &str, route: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tgin-reload-{}-{}.ron", name, std::process::id()));
+        std::fs::write(&path, format!("(updates: [{}], route: {})", updates, route)).unwrap();
+        path
+    }
+
+    fn reloader(path: &Path) -> (Reloader, Arc<dyn RouteableComponent>) {
+        let conf = load_config(path.to_str().unwrap());
+        let settings = StartupSettings::of(&conf);
+        let mut routes = RouteBuilder::default();
+        let route = routes.build(conf.route).unwrap();
+        (Reloader::new(path.to_str().unwrap().to_string(), settings, conf.updates, routes), route)
+    }
+
+    async fn pending(route: &Arc<dyn RouteableComponent>) -> Vec<usize> {
+        let mut pending = Vec::new();
+        for node in descendants(route).await {
+            if node.child(0).await.is_none() {
+                pending.push(node.pending().await);
+            }
+        }
+        pending
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_buffers_survive_a_reload() {
+        let path = write_config(
+            "buffers",
+            "",
+            r#"RoundRobinLB(routes: [LongPollRoute(path: "/reload/a"), LongPollRoute(path: "/reload/b")])"#,
+        );
+        let (mut reloader, route) = reloader(&path);
+
+        let a = route.child(0).await.unwrap();
+        a.process(json!({"update_id": 1, "message": {}})).await;
+        a.process(json!({"update_id": 2, "message": {}})).await;
+
+        // b is removed, c is added, a moves and gets a weight
+        std::fs::write(&path, r#"(updates: [], route: WeightedRoundRobinLB(routes: [
+            (route: LongPollRoute(path: "/reload/c"), weight: 1),
+            (route: LongPollRoute(path: "/reload/a"), weight: 3),
+        ]))"#).unwrap();
+        let reload = reloader.reload().unwrap();
+
+        assert_eq!(pending(&reload.route).await, vec![0, 2]);
+        assert_eq!(reload.route.json_struct().await["routes"][1]["weight"], 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_removed_long_poll_route_is_forgotten() {
+        let path = write_config(
+            "forgotten",
+            "",
+            r#"RoundRobinLB(routes: [LongPollRoute(path: "/reload/gone"), LongPollRoute(path: "/reload/stays")])"#,
+        );
+        let (mut reloader, route) = reloader(&path);
+        route.child(0).await.unwrap().process(json!({"update_id": 1, "message": {}})).await;
+
+        std::fs::write(&path, r#"(updates: [], route: LongPollRoute(path: "/reload/stays"))"#).unwrap();
+        reloader.reload().unwrap();
+
+        // back in the tree, the route starts with an empty buffer
+        std::fs::write(&path, r#"(updates: [], route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/reload/gone"), LongPollRoute(path: "/reload/stays"),
+        ]))"#).unwrap();
+        let reload = reloader.reload().unwrap();
+        assert_eq!(pending(&reload.route).await, vec![0, 0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_broken_config_keeps_running_config() {
+        let path = write_config("broken", "", r#"LongPollRoute(path: "/reload/broken")"#);
+        let (mut reloader, _) = reloader(&path);
+
+        std::fs::write(&path, "(updates: [], route: LongPollRoute(").unwrap();
+        assert!(reloader.reload().err().unwrap().contains("Failed to parse RON config"));
+
+        std::fs::write(&path, r#"(updates: [], route: LongPollRoute(path: "${TGIN_RELOAD_UNSET_VAR}"))"#).unwrap();
+        assert!(reloader.reload().err().unwrap().contains("TGIN_RELOAD_UNSET_VAR"));
+
+        // a write-ahead log that cannot be opened, its dir is a file
+        std::fs::write(&path, format!(
+            r#"(updates: [], route: LongPollRoute(path: "/reload/broken/wal", wal: Some(WalConfig(dir: "{}"))))"#,
+            path.display(),
+        )).unwrap();
+        assert!(reloader.reload().err().unwrap().contains("Failed to open write-ahead log"));
+
+        std::fs::write(&path, r#"(updates: [], route: LongPollRoute(path: "/reload/broken"))"#).unwrap();
+        assert!(reloader.reload().is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_only_changed_providers_are_restarted() {
+        let route = r#"WebhookRoute(url: "http://127.0.0.1:1/reload/providers")"#;
+        let path = write_config(
+            "providers",
+            r#"LongPollUpdate(token: "1:a"), LongPollUpdate(token: "2:b"), WebhookUpdate(path: "/reload/hook")"#,
+            route,
+        );
+        let (mut reloader, _) = reloader(&path);
+
+        // 1:a is unchanged, 2:b gets a new limit, 3:c is new, the webhook provider stays
+        std::fs::write(&path, format!(
+            r#"(updates: [LongPollUpdate(token: "3:c"), LongPollUpdate(token: "2:b", limit: 10), WebhookUpdate(path: "/reload/hook"), LongPollUpdate(token: "1:a")], route: {})"#,
+            route,
+        )).unwrap();
+        let reload = reloader.reload().unwrap();
+
+        let changes: Vec<Option<usize>> = reload.updates.iter().map(|change| match change {
+            ProviderChange::Keep(i) => Some(*i),
+            ProviderChange::Start(_) => None,
+        }).collect();
+        assert_eq!(changes, vec![Some(2), None, None, Some(0)]);
+
+        // a second reload without changes keeps everything
+        let reload = reloader.reload().unwrap();
+        assert!(reload.updates.iter().all(|change| matches!(change, ProviderChange::Keep(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_changed_webhook_providers_are_refused() {
+        let route = r#"LongPollRoute(path: "/reload/webhooks")"#;
+        let path = write_config("webhooks", r#"WebhookUpdate(path: "/reload/webhooks/a")"#, route);
+        let (mut reloader, _) = reloader(&path);
+
+        std::fs::write(&path, format!(
+            r#"(updates: [WebhookUpdate(path: "/reload/webhooks/b")], route: {})"#,
+            route,
+        )).unwrap();
+        let err = reloader.reload().err().expect("A changed webhook provider must be refused");
+        assert!(err.contains("WebhookUpdate"));
+
+        // the running providers are untouched, so the old config still reloads
+        std::fs::write(&path, format!(
+            r#"(updates: [WebhookUpdate(path: "/reload/webhooks/a")], route: {})"#,
+            route,
+        )).unwrap();
+        assert!(reloader.reload().is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_route_of_several_bots_is_refused() {
+        let route = r#"LongPollRoute(path: "/reload/bots")"#;
+        let path = write_config("bots", r#"LongPollUpdate(token: "1:a")"#, route);
+        let (mut reloader, _) = reloader(&path);
+
+        std::fs::write(&path, format!(
+            r#"(updates: [LongPollUpdate(token: "1:a"), LongPollUpdate(token: "2:b")], route: {})"#,
+            route,
+        )).unwrap();
+        let err = reloader.reload().err().expect("A long-poll route of two bots must be refused");
+        assert!(err.contains("several bots"));
+
+        // a second token of the same bot is fine
+        std::fs::write(&path, format!(
+            r#"(updates: [LongPollUpdate(token: "1:a"), LongPollUpdate(token: "1:b")], route: {})"#,
+            route,
+        )).unwrap();
+        assert!(reloader.reload().is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_changed_startup_settings() {
+        let settings = |extra: &str| {
+            let conf: TginConfig = ron::from_str(&format!(
+                r#"(updates: [], route: LongPollRoute(path: "/reload/settings"){})"#,
+                extra,
+            )).unwrap();
+            StartupSettings::of(&conf)
+        };
+
+        let running = settings("");
+        assert!(running.changed(&settings("")).is_empty());
+        assert_eq!(
+            running.changed(&settings(", max_in_flight: 5, shutdown: ShutdownConfig(timeout: 1), ordered_by: Some(Chat)")),
+            vec!["ordered_by", "shutdown", "max_in_flight"],
+        );
+        assert_eq!(
+            running.changed(&settings(r#", dark_threads: 8, server_port: Some(8080), api: Some(ApiConfig(base_path: "/api"))"#)),
+            vec!["dark_threads", "server_port", "api"],
+        );
+    }
+}
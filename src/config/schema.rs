@@ -1,4 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::utils::defaults::TELEGRAM_UPDATE_TYPES;
 
 #[derive(Deserialize, Debug)]
 pub struct TginConfig {
@@ -10,24 +13,77 @@ pub struct TginConfig {
     pub updates: Vec<UpdateConfig>,
     pub route: RouteConfig,
     pub api: Option<ApiConfig>,
+    #[serde(default)]
+    pub dedup: Option<DedupConfig>,
+    #[serde(default)]
+    pub ordered_by: Option<UpdateKeyConfig>,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
 }
 
 fn default_workers() -> usize {
     4
 }
 
-#[derive(Deserialize, Debug)]
+fn default_max_in_flight() -> usize {
+    10000
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct SslConfig {
     pub cert: String,
     pub key: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ShutdownConfig {
+    #[serde(default = "default_shutdown_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_drain_window")]
+    pub drain_window: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_shutdown_timeout(),
+            drain_window: default_drain_window(),
+        }
+    }
+}
+
+fn default_shutdown_timeout() -> u64 {
+    5000
+}
+
+fn default_drain_window() -> u64 {
+    3000
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DedupConfig {
+    #[serde(default = "default_dedup_ttl")]
+    pub ttl: u64,
+    #[serde(default = "default_dedup_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_dedup_ttl() -> u64 {
+    600000
+}
+
+fn default_dedup_max_entries() -> usize {
+    100000
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ApiConfig {
     pub base_path: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub enum UpdateConfig {
     LongPollUpdate {
         token: String,
@@ -36,27 +92,80 @@ pub enum UpdateConfig {
         default_timeout_sleep: u64,
         #[serde(default = "default_timeout")]
         error_timeout_sleep: u64,
+        #[serde(default)]
+        offset_file: Option<String>,
+        #[serde(default)]
+        delete_webhook_on_conflict: bool,
+        #[serde(default = "default_poll_timeout")]
+        poll_timeout: u64,
+        #[serde(default = "default_limit")]
+        limit: u64,
+        #[serde(default)]
+        allowed_updates: Option<Vec<String>>,
     },
     WebhookUpdate {
         path: String,
         registration: Option<RegistrationWebhookConfig>,
+        #[serde(default)]
+        secret_token: Option<String>,
+        #[serde(default)]
+        allowed_ips: Option<Vec<String>>,
+        #[serde(default)]
+        trusted_proxy: Option<TrustedProxyConfig>,
+        #[serde(default = "default_max_body_size")]
+        max_body_size: usize,
+        #[serde(default = "default_read_timeout")]
+        read_timeout: u64,
+        #[serde(default)]
+        bot: Option<String>,
     },
 }
 
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+fn default_read_timeout() -> u64 {
+    10000
+}
+
+fn default_poll_timeout() -> u64 {
+    30
+}
+
+fn default_limit() -> u64 {
+    100
+}
+
 fn default_timeout() -> u64 {
     100
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TrustedProxyConfig {
+    pub header: String,
+    pub proxies: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct RegistrationWebhookConfig {
     pub public_ip: String,
     pub set_webhook_url: Option<String>,
     pub token: String,
-}
-#[derive(Deserialize, Debug)]
-pub enum RouteStrategyConfig {
-    RoundRobinLB { routes: Vec<RouteConfig> },
-    AllLB { routes: Vec<RouteConfig> },
+    #[serde(default)]
+    pub secret_token: Option<String>,
+    #[serde(default)]
+    pub allowed_updates: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_connections: Option<u32>,
+    #[serde(default)]
+    pub drop_pending_updates: bool,
+    #[serde(default)]
+    pub ip_address: Option<String>,
+    #[serde(default)]
+    pub upload_certificate: bool,
+    #[serde(default)]
+    pub delete_on_shutdown: bool,
 }
 
 
@@ -65,9 +174,261 @@ pub enum RouteStrategyConfig {
 
 #[derive(Deserialize, Debug)]
 pub enum RouteConfig {
-    LongPollRoute { path: String },
-    WebhookRoute { url: String },
+    LongPollRoute {
+        path: String,
+        #[serde(default)]
+        max_poll_age: Option<u64>,
+        #[serde(default)]
+        max_len: Option<usize>,
+        #[serde(default)]
+        max_bytes: Option<usize>,
+        #[serde(default)]
+        overflow: OverflowPolicyConfig,
+        #[serde(default)]
+        wal: Option<WalConfig>,
+    },
+    WebhookRoute {
+        url: String,
+        #[serde(default = "default_webhook_timeout")]
+        timeout: u64,
+        #[serde(default)]
+        retry: Option<RetryConfig>,
+        #[serde(default)]
+        dead_letter: Option<Box<RouteConfig>>,
+        #[serde(default)]
+        circuit_breaker: Option<CircuitBreakerConfig>,
+        #[serde(default)]
+        health_check: Option<HealthCheckConfig>,
+    },
     
     RoundRobinLB { routes: Vec<RouteConfig> },
     AllLB { routes: Vec<RouteConfig> },
-}
\ No newline at end of file
+    WeightedRoundRobinLB { routes: Vec<WeightedRouteConfig> },
+    LeastPendingLB {
+        routes: Vec<RouteConfig>,
+        #[serde(default)]
+        mode: LeastPendingModeConfig,
+    },
+    FailoverLB {
+        routes: Vec<RouteConfig>,
+        #[serde(default = "default_failover_cooldown")]
+        cooldown: u64,
+    },
+    HashLB {
+        routes: Vec<RouteConfig>,
+        #[serde(default)]
+        key: UpdateKeyConfig,
+    },
+
+    UpdateTypeRouter {
+        routes: Vec<UpdateTypeRouteConfig>,
+        #[serde(default)]
+        default: Option<Box<RouteConfig>>,
+    },
+    CommandRouter {
+        #[serde(default)]
+        bot_username: Option<String>,
+        routes: Vec<CommandRouteConfig>,
+        #[serde(default)]
+        default: Option<Box<RouteConfig>>,
+    },
+}
+
+pub fn default_webhook_timeout() -> u64 {
+    10000
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WeightedRouteConfig {
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub route: RouteConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_base_backoff")]
+    pub base_backoff: u64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+    #[serde(default)]
+    pub jitter: f64,
+    #[serde(default = "default_retry_statuses")]
+    pub retry_statuses: Vec<u16>,
+    #[serde(default = "default_honor_retry_after")]
+    pub honor_retry_after: bool,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_base_backoff() -> u64 {
+    100
+}
+
+fn default_max_backoff() -> u64 {
+    10000
+}
+
+fn default_retry_statuses() -> Vec<u16> {
+    vec![429, 500, 502, 503, 504]
+}
+
+fn default_honor_retry_after() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_error_rate")]
+    pub error_rate: f64,
+    #[serde(default = "default_window")]
+    pub window: usize,
+    #[serde(default = "default_breaker_cooldown")]
+    pub cooldown: u64,
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_error_rate() -> f64 {
+    0.5
+}
+
+fn default_window() -> usize {
+    20
+}
+
+fn default_breaker_cooldown() -> u64 {
+    30000
+}
+
+fn default_half_open_probes() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HealthCheckConfig {
+    pub url: String,
+    #[serde(default = "default_health_interval")]
+    pub interval: u64,
+    #[serde(default = "default_health_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+}
+
+fn default_health_interval() -> u64 {
+    10000
+}
+
+fn default_health_timeout() -> u64 {
+    2000
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WalConfig {
+    pub dir: String,
+    #[serde(default = "default_segment_size")]
+    pub segment_size: u64,
+    #[serde(default)]
+    pub fsync: bool,
+}
+
+fn default_segment_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_failover_cooldown() -> u64 {
+    5000
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateTypeRouteConfig {
+    #[serde(deserialize_with = "update_types")]
+    pub updates: Vec<String>,
+    pub route: RouteConfig,
+}
+
+/// A misspelled kind would never match and quietly send its updates to `default`.
+fn update_types<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let updates = Vec::<String>::deserialize(deserializer)?;
+    match updates.iter().find(|kind| !TELEGRAM_UPDATE_TYPES.contains(&kind.as_str())) {
+        Some(kind) => Err(D::Error::custom(format!("unknown update type '{}'", kind))),
+        None => Ok(updates),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommandRouteConfig {
+    #[serde(default)]
+    pub commands: Vec<String>,
+    #[serde(default)]
+    pub callback_prefixes: Vec<String>,
+    #[serde(default)]
+    pub inline_prefixes: Vec<String>,
+    pub route: RouteConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub enum LeastPendingModeConfig {
+    #[default]
+    Least,
+    PowerOfTwoChoices,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub enum OverflowPolicyConfig {
+    #[default]
+    DropOldest,
+    DropNewest,
+    Reject,
+    Block,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub enum UpdateKeyConfig {
+    #[default]
+    Chat,
+    User,
+    Path(String),
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_update_type_is_rejected() {
+        let route = r#"UpdateTypeRouter(routes: [(updates: ["message", "callback_query"], route: LongPollRoute(path: "/a"))])"#;
+        assert!(ron::from_str::<RouteConfig>(route).is_ok());
+
+        let typo = r#"UpdateTypeRouter(routes: [(updates: ["message", "callback_querry"], route: LongPollRoute(path: "/a"))])"#;
+        let err = ron::from_str::<RouteConfig>(typo).unwrap_err();
+        assert!(err.to_string().contains("unknown update type 'callback_querry'"));
+    }
+}
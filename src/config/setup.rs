@@ -1,89 +1,380 @@
 use crate::base::{RouteableComponent, UpdaterComponent};
-use crate::lb::{roundrobin::RoundRobinLB, all::AllLB};
+use crate::lb::{roundrobin::RoundRobinLB, all::AllLB, hash::HashLB, weighted::WeightedRoundRobinLB};
+use crate::lb::leastpending::{LeastPendingLB, LeastPendingMode};
+use crate::lb::failover::FailoverLB;
+use crate::router::updatetype::{UpdateTypeRouter, UpdateTypeBranch};
+use crate::router::command::{CommandRouter, CommandBranch};
 use crate::route::longpull::LongPollRoute;
 use crate::route::webhook::WebhookRoute;
+use crate::route::retry::RetryPolicy;
+use crate::route::breaker::{CircuitBreaker, BreakerPolicy};
+use crate::route::health::{HealthCheck, HealthPolicy};
+use crate::route::buffer::{BufferLimits, OverflowPolicy};
+use crate::route::wal::WalPolicy;
 use crate::update::longpull::LongPollUpdate;
-use crate::update::webhook::{WebhookUpdate};
-use crate::config::schema::{TginConfig, UpdateConfig,RouteConfig};
+use crate::update::base::bot_id;
+use crate::update::allowlist::{IpAllowlist, TrustedProxy, parse_ranges};
+use crate::update::webhook::{WebhookUpdate, RegistrationWebhookConfig as WebhookRegistration};
+use crate::config::schema::{TginConfig, UpdateConfig, RouteConfig, RegistrationWebhookConfig, SslConfig, UpdateKeyConfig, LeastPendingModeConfig, OverflowPolicyConfig};
+use crate::utils::update::UpdateKey;
+use crate::utils::defaults::TELEGRAM_UPDATE_TYPES;
+use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use std::env;
 use regex::Regex;
 
 pub fn load_config(path: &str) -> TginConfig {
-    let content = fs::read_to_string(path).expect("Failed to read config file");
-    let processed_content = substitute_env_vars(&content);
+    try_load_config(path).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like [`load_config`], but reports a broken file instead of panicking, so a reload can keep
+/// the running config.
+pub fn try_load_config(path: &str) -> Result<TginConfig, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("Failed to read config file: {}", err))?;
+    let processed_content = substitute_env_vars(&content)?;
 
-    ron::from_str(&processed_content).expect("Failed to parse RON config")
+    ron::from_str(&processed_content).map_err(|err| format!("Failed to parse RON config: {}", err))
 }
 
 
-fn substitute_env_vars(input: &str) -> String {
+fn substitute_env_vars(input: &str) -> Result<String, String> {
     let re = Regex::new(r"\$\{(\w+)\}").unwrap();
+    let mut missing = None;
 
-    re.replace_all(input, |caps: &regex::Captures| {
+    let output = re.replace_all(input, |caps: &regex::Captures| {
         let var_name = &caps[1];
         
         match env::var(var_name) {
             Ok(val) => val,
             Err(_) => {
-                panic!("Environment variable '${}' is not set", var_name);
+                missing.get_or_insert_with(|| var_name.to_string());
+                String::new()
             }
         }
-    }).to_string()
+    }).to_string();
+
+    match missing {
+        Some(var_name) => Err(format!("Environment variable '${}' is not set", var_name)),
+        None => Ok(output),
+    }
 }
 
-pub fn build_updates(configs: Vec<UpdateConfig>) -> Vec<Box<dyn UpdaterComponent>> {
+pub fn build_updates(configs: Vec<UpdateConfig>, ssl: Option<&SslConfig>) -> Result<Vec<Box<dyn UpdaterComponent>>, String> {
     let mut result: Vec<Box<dyn UpdaterComponent>> = Vec::new();
 
     for cfg in configs {
         match cfg {
-            UpdateConfig::LongPollUpdate { token, url, default_timeout_sleep, error_timeout_sleep } => {
+            UpdateConfig::LongPollUpdate {
+                token, url, default_timeout_sleep, error_timeout_sleep, offset_file, delete_webhook_on_conflict,
+                poll_timeout, limit, allowed_updates,
+            } => {
                 let mut up = LongPollUpdate::new(token);
                 if let Some(u) = url {
                     up.set_url(u); 
                 }
+                if let Some(offset_file) = offset_file {
+                    up.set_offset_file(PathBuf::from(offset_file));
+                }
+                up.set_delete_webhook_on_conflict(delete_webhook_on_conflict);
+                for kind in allowed_updates.iter().flatten() {
+                    if !TELEGRAM_UPDATE_TYPES.contains(&kind.as_str()) {
+                        eprintln!("Unknown update type '{}' in allowed_updates, passing it to Telegram as is", kind);
+                    }
+                }
+                up.set_poll_params(poll_timeout, limit, allowed_updates);
                 up.set_timeouts(default_timeout_sleep, error_timeout_sleep); 
                 result.push(Box::new(up));
             }
-            UpdateConfig::WebhookUpdate { path, registration } => {
+            UpdateConfig::WebhookUpdate {
+                path, registration, secret_token, allowed_ips, trusted_proxy, max_body_size, read_timeout, bot,
+            } => {
                 let mut up = WebhookUpdate::new(path);
+                if let Some(bot) = bot {
+                    up.set_bot(bot_id(&bot));
+                }
+                up.set_limits(max_body_size, Duration::from_millis(read_timeout));
+                if let Some(secret_token) = secret_token {
+                    up.set_secret_token(secret_token);
+                }
+                if let Some(allowed_ips) = allowed_ips {
+                    let ranges = parse_ranges(&allowed_ips)
+                        .map_err(|err| format!("Invalid allowed_ips: {}", err))?;
+                    let mut allowlist = IpAllowlist::new(ranges);
+                    if let Some(proxy) = trusted_proxy {
+                        let proxies = parse_ranges(&proxy.proxies)
+                            .map_err(|err| format!("Invalid trusted_proxy.proxies: {}", err))?;
+                        allowlist.set_trusted_proxy(TrustedProxy { header: proxy.header.to_lowercase(), proxies });
+                    }
+                    up.set_allowlist(allowlist);
+                } else if trusted_proxy.is_some() {
+                    eprintln!("trusted_proxy has no effect without allowed_ips");
+                }
                 if let Some(reg) = registration {
+                    up.set_registration(build_registration(reg, ssl)?);
                 }
                 result.push(Box::new(up));
             }
         }
     }
-    result
+    Ok(result)
 }
 
-pub fn build_route(cfg: RouteConfig) -> Arc<dyn RouteableComponent> {
+/// Bot a provider config belongs to, when the config tells.
+fn config_bot(cfg: &UpdateConfig) -> Option<String> {
     match cfg {
-        RouteConfig::LongPollRoute { path } => {
-            Arc::new(LongPollRoute::new(path))
+        UpdateConfig::LongPollUpdate { token, .. } => Some(bot_id(token)),
+        UpdateConfig::WebhookUpdate { bot, registration, .. } => bot.as_deref()
+            .or(registration.as_ref().map(|reg| reg.token.as_str()))
+            .map(bot_id),
+    }
+}
+
+/// A `LongPollRoute` confirms its buffer by `update_id`, and those are only ordered within one
+/// bot, so a consumer's offset would drop the updates of another bot. Every update reaches the
+/// whole tree, so a tree with long-poll routes may only be fed by one bot. Providers that do not
+/// name their bot are not counted.
+pub fn check_longpoll_bots(updates: &[UpdateConfig], routes: &RouteBuilder) -> Result<(), String> {
+    if routes.longpoll.is_empty() {
+        return Ok(());
+    }
+    let mut bots: Vec<String> = updates.iter().filter_map(config_bot).collect();
+    bots.sort();
+    bots.dedup();
+    if bots.len() > 1 {
+        return Err(format!(
+            "LongPollRoute cannot serve updates of several bots ({}), their update_ids would confirm each other's updates",
+            bots.join(", "),
+        ));
+    }
+    Ok(())
+}
+
+fn build_registration(cfg: RegistrationWebhookConfig, ssl: Option<&SslConfig>) -> Result<WebhookRegistration, String> {
+    let mut reg = WebhookRegistration::new(cfg.token, cfg.public_ip);
+    if let Some(url) = cfg.set_webhook_url {
+        reg.set_webhook_url(url);
+    }
+    for kind in cfg.allowed_updates.iter().flatten() {
+        if !TELEGRAM_UPDATE_TYPES.contains(&kind.as_str()) {
+            eprintln!("Unknown update type '{}' in allowed_updates, passing it to Telegram as is", kind);
         }
-        RouteConfig::WebhookRoute { url } => {
-            Arc::new(WebhookRoute::new(url))
+    }
+    if cfg.upload_certificate {
+        match ssl {
+            Some(ssl) => reg.certificate = Some(PathBuf::from(&ssl.cert)),
+            None => return Err("upload_certificate is set but the ssl section with a certificate is missing".to_string()),
         }
+    }
+    reg.secret_token = cfg.secret_token;
+    reg.allowed_updates = cfg.allowed_updates;
+    reg.max_connections = cfg.max_connections;
+    reg.drop_pending_updates = cfg.drop_pending_updates;
+    reg.ip_address = cfg.ip_address;
+    reg.delete_on_shutdown = cfg.delete_on_shutdown;
+    Ok(reg)
+}
+
+/// Builds route trees and remembers what it built, so a reloaded tree takes over the routes
+/// whose identity did not change: a `LongPollRoute` with the path of one in the previous tree or
+/// still served keeps its buffer and consumers, a `WebhookRoute` with the same url keeps its dead
+/// letters. Routes of the previous tree that are not in the new one are forgotten.
+#[derive(Default, Clone)]
+pub struct RouteBuilder {
+    longpoll: HashMap<String, LongPollRoute>,
+    webhook: HashMap<String, Arc<WebhookRoute>>,
+}
+
+impl RouteBuilder {
+    /// Builds the tree for `cfg`. An invalid config is reported and leaves the builder as it was.
+    pub fn build(&mut self, cfg: RouteConfig) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut builder = RouteBuilder::default();
+        let route = builder.build_node(cfg, self)?;
+        *self = builder;
+        Ok(route)
+    }
+
+    fn build_node(&mut self, cfg: RouteConfig, previous: &RouteBuilder) -> Result<Arc<dyn RouteableComponent>, String> {
+        match cfg {
+            RouteConfig::LongPollRoute { path, max_poll_age, max_len, max_bytes, overflow, wal } => {
+                let registered = LONGPOLL_REGISTRY.read().expect("Registry lock poisoned").get(&path).map(|route| (**route).clone());
+                let existing = registered
+                    .or_else(|| self.longpoll.get(&path).cloned())
+                    .or_else(|| previous.longpoll.get(&path).cloned());
+                let is_new = existing.is_none();
+                let mut route = existing.unwrap_or_else(|| LongPollRoute::new(path.clone()));
+                route.set_max_poll_age(max_poll_age.map(Duration::from_secs));
+                route.set_limits(BufferLimits {
+                    max_len,
+                    max_bytes,
+                    overflow: match overflow {
+                        OverflowPolicyConfig::DropOldest => OverflowPolicy::DropOldest,
+                        OverflowPolicyConfig::DropNewest => OverflowPolicy::DropNewest,
+                        OverflowPolicyConfig::Reject => OverflowPolicy::Reject,
+                        OverflowPolicyConfig::Block => OverflowPolicy::Block,
+                    },
+                });
+                match wal {
+                    Some(wal) if is_new => {
+                        route
+                            .set_wal(WalPolicy {
+                                dir: PathBuf::from(&wal.dir),
+                                segment_size: wal.segment_size.max(1),
+                                fsync: wal.fsync,
+                            })
+                            .map_err(|err| format!("Failed to open write-ahead log in {}: {}", wal.dir, err))?;
+                    }
+                    _ if wal.is_some() != route.is_persistent() => {
+                        eprintln!("Write-ahead log of long-poll route {} can only be turned on or off with a restart", path);
+                    }
+                    _ => {}
+                }
+                self.longpoll.insert(path, route.clone());
+                Ok(Arc::new(route))
+            }
+            RouteConfig::WebhookRoute { url, timeout, retry, dead_letter, circuit_breaker, health_check } => {
+                let mut route = WebhookRoute::new(url.clone());
+                route.set_timeout(Duration::from_millis(timeout));
+                if let Some(previous) = previous.webhook.get(&url) {
+                    route.keep_dead_letters(previous);
+                }
+                if let Some(retry) = retry {
+                    route.set_retry(RetryPolicy {
+                        max_attempts: retry.max_attempts.max(1),
+                        base_backoff: Duration::from_millis(retry.base_backoff),
+                        max_backoff: Duration::from_millis(retry.max_backoff),
+                        jitter: retry.jitter,
+                        retry_statuses: retry.retry_statuses,
+                        honor_retry_after: retry.honor_retry_after,
+                    });
+                }
+                if let Some(dead_letter) = dead_letter {
+                    route.set_dead_letter(self.build_node(*dead_letter, previous)?);
+                }
+                if let Some(breaker) = circuit_breaker {
+                    route.set_breaker(CircuitBreaker::new(BreakerPolicy {
+                        failure_threshold: breaker.failure_threshold.max(1),
+                        error_rate: breaker.error_rate,
+                        window: breaker.window.max(1),
+                        cooldown: Duration::from_millis(breaker.cooldown),
+                        half_open_probes: breaker.half_open_probes.max(1),
+                    }));
+                }
+                if let Some(health) = health_check {
+                    route.set_health_check(HealthCheck::new(HealthPolicy {
+                        url: health.url,
+                        interval: Duration::from_millis(health.interval.max(1)),
+                        timeout: Duration::from_millis(health.timeout),
+                        healthy_threshold: health.healthy_threshold.max(1),
+                        unhealthy_threshold: health.unhealthy_threshold.max(1),
+                    }));
+                }
+                let route = Arc::new(route);
+                self.webhook.insert(url, route.clone());
+                Ok(route)
+            }
         
-        RouteConfig::RoundRobinLB { routes } => {
-            let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
-                .into_iter()
-                .map(build_route) 
-                .collect();
+            RouteConfig::RoundRobinLB { routes } => {
+                let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
+                    .into_iter()
+                    .map(|r| self.build_node(r, previous))
+                    .collect::<Result<_, _>>()?;
             
-            Arc::new(RoundRobinLB::new(built_routes))
-        }
+                Ok(Arc::new(RoundRobinLB::new(built_routes)))
+            }
         
-        RouteConfig::AllLB { routes } => {
-            let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
-                .into_iter()
-                .map(build_route) 
-                .collect();
+            RouteConfig::AllLB { routes } => {
+                let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
+                    .into_iter()
+                    .map(|r| self.build_node(r, previous))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Arc::new(AllLB::new(built_routes)))
+            }
+
+            RouteConfig::WeightedRoundRobinLB { routes } => {
+                let built_routes: Vec<(Arc<dyn RouteableComponent>, u32)> = routes
+                    .into_iter()
+                    .map(|r| Ok((self.build_node(r.route, previous)?, r.weight)))
+                    .collect::<Result<_, String>>()?;
+
+                Ok(Arc::new(WeightedRoundRobinLB::new(built_routes)))
+            }
+
+            RouteConfig::LeastPendingLB { routes, mode } => {
+                let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
+                    .into_iter()
+                    .map(|r| self.build_node(r, previous))
+                    .collect::<Result<_, _>>()?;
+
+                let mode = match mode {
+                    LeastPendingModeConfig::Least => LeastPendingMode::Least,
+                    LeastPendingModeConfig::PowerOfTwoChoices => LeastPendingMode::PowerOfTwoChoices,
+                };
+
+                Ok(Arc::new(LeastPendingLB::new(built_routes, mode)))
+            }
+
+            RouteConfig::FailoverLB { routes, cooldown } => {
+                let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
+                    .into_iter()
+                    .map(|r| self.build_node(r, previous))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Arc::new(FailoverLB::new(built_routes, Duration::from_millis(cooldown))))
+            }
 
-            Arc::new(AllLB::new(built_routes))
+            RouteConfig::HashLB { routes, key } => {
+                let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
+                    .into_iter()
+                    .map(|r| self.build_node(r, previous))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Arc::new(HashLB::new(built_routes, build_update_key(key))))
+            }
+
+            RouteConfig::UpdateTypeRouter { routes, default } => {
+                let branches: Vec<UpdateTypeBranch> = routes
+                    .into_iter()
+                    .map(|branch| Ok(UpdateTypeBranch {
+                        updates: branch.updates,
+                        route: self.build_node(branch.route, previous)?,
+                    }))
+                    .collect::<Result<_, String>>()?;
+
+                let default = default.map(|route| self.build_node(*route, previous)).transpose()?;
+
+                Ok(Arc::new(UpdateTypeRouter::new(branches, default)))
+            }
+
+            RouteConfig::CommandRouter { bot_username, routes, default } => {
+                let branches: Vec<CommandBranch> = routes
+                    .into_iter()
+                    .map(|branch| Ok(CommandBranch {
+                        commands: branch.commands,
+                        callback_prefixes: branch.callback_prefixes,
+                        inline_prefixes: branch.inline_prefixes,
+                        route: self.build_node(branch.route, previous)?,
+                    }))
+                    .collect::<Result<_, String>>()?;
+
+                let default = default.map(|route| self.build_node(*route, previous)).transpose()?;
+
+                Ok(Arc::new(CommandRouter::new(bot_username, branches, default)))
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn build_update_key(cfg: UpdateKeyConfig) -> UpdateKey {
+    match cfg {
+        UpdateKeyConfig::Chat => UpdateKey::Chat,
+        UpdateKeyConfig::User => UpdateKey::User,
+        UpdateKeyConfig::Path(path) => UpdateKey::path(&path),
+    }
+}
@@ -0,0 +1,92 @@
+use crate::utils::update::update_id;
+
+use serde_json::Value;
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+#[derive(Default)]
+struct Seen {
+    keys: HashSet<(String, i64)>,
+    order: VecDeque<(Instant, (String, i64))>,
+}
+
+/// Set of recently seen `(bot, update_id)` pairs. A pair is forgotten once it is older than
+/// `ttl` or when more than `max_entries` newer pairs were seen after it.
+pub struct Dedup {
+    ttl: Duration,
+    max_entries: usize,
+    seen: Mutex<Seen>,
+}
+
+impl Dedup {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self { ttl, max_entries, seen: Mutex::new(Seen::default()) }
+    }
+
+    /// Remembers the update and tells whether it was not seen before.
+    /// Updates without an `update_id` cannot be matched and always pass.
+    pub fn is_new(&self, bot: &str, update: &Value) -> bool {
+        let Some(id) = update_id(update) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let mut seen = self.seen.lock().expect("Dedup lock poisoned");
+
+        while let Some((at, _)) = seen.order.front() {
+            if now.duration_since(*at) < self.ttl && seen.order.len() < self.max_entries {
+                break;
+            }
+            if let Some((_, key)) = seen.order.pop_front() {
+                seen.keys.remove(&key);
+            }
+        }
+
+        let key = (bot.to_string(), id);
+        if !seen.keys.insert(key.clone()) {
+            return false;
+        }
+        seen.order.push_back((now, key));
+        true
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_duplicates_per_bot() {
+        let dedup = Dedup::new(Duration::from_secs(60), 100);
+
+        assert!(dedup.is_new("1", &json!({"update_id": 10})));
+        assert!(!dedup.is_new("1", &json!({"update_id": 10})));
+        assert!(dedup.is_new("2", &json!({"update_id": 10})));
+        assert!(dedup.is_new("1", &json!({"update_id": 11})));
+
+        assert!(dedup.is_new("1", &json!({"message": {}})));
+        assert!(dedup.is_new("1", &json!({"message": {}})));
+    }
+
+    #[test]
+    fn test_bounded_by_size_and_time() {
+        let dedup = Dedup::new(Duration::from_secs(60), 2);
+        assert!(dedup.is_new("1", &json!({"update_id": 1})));
+        assert!(dedup.is_new("1", &json!({"update_id": 2})));
+        assert!(dedup.is_new("1", &json!({"update_id": 3})));
+        // pushed out by newer updates
+        assert!(dedup.is_new("1", &json!({"update_id": 1})));
+
+        let dedup = Dedup::new(Duration::from_millis(20), 100);
+        assert!(dedup.is_new("1", &json!({"update_id": 1})));
+        assert!(!dedup.is_new("1", &json!({"update_id": 1})));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(dedup.is_new("1", &json!({"update_id": 1})));
+    }
+}
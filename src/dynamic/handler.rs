@@ -1,41 +1,47 @@
 
-use axum::http::{Method, header::CONTENT_TYPE};
-use axum::{extract::{Request}, Json}; 
-use serde_json::{json, Value};
+use axum::http::{Method, StatusCode, header::CONTENT_TYPE};
+use axum::{extract::{Request}, Json};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
 
 use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
 
 use crate::route::longpull::GetUpdatesParams;
 
 
+fn error(status: StatusCode, description: String) -> Response {
+    let body = Json(json!({
+        "ok": false,
+        "error_code": status.as_u16(),
+        "description": description
+    }));
+    (status, body).into_response()
+}
+
+/// Serves long-poll routes that were not bound to the server at startup. Any other path is a 404.
 pub async fn dynamic_handler(
-    request: Request, 
-) -> Json<Value> {
+    request: Request,
+) -> Response {
     let (parts, body) = request.into_parts();
-    
-    let method = parts.method;
-    let uri = parts.uri;
-    let headers = parts.headers;
-    let path = uri.path().to_string();
 
-    let body_bytes = match axum::body::to_bytes(body, usize::MAX).await {
-        Ok(b) => b,
-        Err(_) => return Json(json!({
-            "ok": false,
-            "error_code": 400,
-            "description": "failed to read request body"
-        })),
+    let route = {
+        let registry = LONGPOLL_REGISTRY.read().expect("Registry lock poisoned");
+        registry.get(parts.uri.path()).cloned()
+    };
+    let Some(route) = route else {
+        return StatusCode::NOT_FOUND.into_response();
     };
 
-    if method != Method::POST {
-        return Json(json!({ 
-            "ok": false, 
-            "error_code": 405, 
-            "description": "method not allowed" 
-        }));
+    if parts.method != Method::POST {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed".to_string());
     }
 
-    let content_type = headers
+    let body_bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(b) => b,
+        Err(_) => return error(StatusCode::BAD_REQUEST, "failed to read request body".to_string()),
+    };
+
+    let content_type = parts.headers
         .get(CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
         .unwrap_or("");
@@ -43,35 +49,57 @@ pub async fn dynamic_handler(
     let params: GetUpdatesParams = if content_type.contains("application/json") {
         match serde_json::from_slice(&body_bytes) {
             Ok(p) => p,
-            Err(_) => return Json(json!({ 
-                "ok": false, 
-                "error_code": 400, 
-                "description": "invalid json body" 
-            })),
+            Err(_) => return error(StatusCode::BAD_REQUEST, "invalid json body".to_string()),
         }
     } else {
         match serde_urlencoded::from_bytes(&body_bytes) {
             Ok(p) => p,
-            Err(_) => GetUpdatesParams { 
-                offset: None, 
-                timeout: None, 
-                limit: None 
+            Err(_) => GetUpdatesParams {
+                offset: None,
+                timeout: None,
+                limit: None
             },
         }
     };
 
-    let route = {
-        let registry = LONGPOLL_REGISTRY.read().expect("Registry lock poisoned");
-        registry.get(&path).cloned()
-    };
+    route.handle_request(params).await.into_response()
+}
+
+
 
-    if let Some(route) = route {
-        return route.handle_request(params).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::Routeable;
+    use crate::route::longpull::LongPollRoute;
+    use axum::{body::Body, Router};
+    use tower::ServiceExt;
+
+    fn poll(path: &str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri(path)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from("timeout=0"))
+            .unwrap()
     }
 
-    Json(json!({ 
-        "ok": false, 
-        "error_code": 404, 
-        "description": format!("Path {} not found in dynamic registry", path)
-    }))
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_serves_only_registered_paths() {
+        let route = LongPollRoute::new("/dynamic/registered".to_string());
+        route.register();
+        let _ = route.process(json!({"update_id": 1})).await;
+        let app: Router = Router::new().fallback(dynamic_handler);
+
+        let response = app.clone().oneshot(poll("/dynamic/unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(poll("/dynamic/registered")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], json!([{"update_id": 1}]));
+
+        LONGPOLL_REGISTRY.write().unwrap().remove("/dynamic/registered");
+    }
+}
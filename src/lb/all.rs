@@ -1,14 +1,12 @@
 
-use crate::base::{Routeable, RouteableComponent, Serverable, Printable};
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
 
 use tokio::sync::{mpsc::Sender, RwLock};
 use axum::{Router};
 
 use std::sync::Arc;
 
-use crate::api::message::AddRouteType;
-use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
-
 use async_trait::async_trait;
 
 use serde_json::{Value, json};
@@ -27,42 +25,95 @@ impl AllLB {
 
 #[async_trait]
 impl Routeable for AllLB {
-    async fn process(&self, update: Value) {
+    async fn process(&self, update: Value) -> DeliveryResult {
         let routes = self.routes.read().await;
+        let mut handles = Vec::with_capacity(routes.len());
         for route in routes.iter() {
+            if !route.is_available().await {
+                continue;
+            }
             let route = route.clone();
             let update = update.clone();
 
-            tokio::spawn(async move {
-                route.process(update).await;
-            });
+            // Dead letters are passed on from the spawned task, a balancer above may hold them.
+            handles.push(tokio::spawn(async move {
+                let mut held = HeldDeadLetters::default();
+                let result = held.attempt(route.process(update)).await;
+                (result, held)
+            }));
+        }
+        drop(routes);
+
+        if handles.is_empty() {
+            return DeliveryResult::Rejected("no available routes".to_string());
+        }
+
+        // Rejected only when every route rejected the update: once one route has it, a
+        // balancer above must not offer it elsewhere, so a partial rejection is a failure.
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            let route_result = match handle.await {
+                Ok((route_result, held)) => {
+                    held.settle(&route_result).await;
+                    route_result
+                }
+                Err(err) => DeliveryResult::BackendError(err.to_string()),
+            };
+            results.push(route_result);
+        }
+
+        if results.iter().all(DeliveryResult::is_rejected) {
+            return results.swap_remove(0);
+        }
+        match results.into_iter().find(|result| !matches!(result, DeliveryResult::Delivered | DeliveryResult::Ignored(_))) {
+            Some(DeliveryResult::Rejected(reason)) => DeliveryResult::BackendError(reason),
+            Some(failure) => failure,
+            None => DeliveryResult::Delivered,
         }
     }
 
 
 
 
-    async fn add_route(&self, route: AddRouteType) -> Result<(), ()>{
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
         let mut routes = self.routes.write().await;
+        let index = index.unwrap_or(routes.len());
+        if index > routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        routes.insert(index, route);
+        Ok(index)
+    }
 
-        match route {
-            AddRouteType::Longpull(route_arc) => {
-                match LONGPOLL_REGISTRY.write() {
-                    Ok(mut registry) => {
-                        registry.insert(route_arc.path.clone(), route_arc.clone());
-                        routes.push(route_arc); 
-                        Ok(())
-                    }
-                    Err(_) => {
-                        Err(())
-                    }
-                }
-            },
-            AddRouteType::Webhook(route) => {
-                routes.push(route); 
-                Ok(())
-            },
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let mut routes = self.routes.write().await;
+        if index >= routes.len() {
+            return Err(RouteError::NotFound);
         }
+        Ok(routes.remove(index))
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = self.routes.read().await;
+        routes.get(index).cloned()
+    }
+
+    async fn pending(&self) -> usize {
+        let routes = self.routes.read().await;
+        let mut pending = 0;
+        for route in routes.iter() {
+            pending += route.pending().await;
+        }
+        pending
+    }
+    async fn is_available(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.is_available().await {
+                return true;
+            }
+        }
+        false
     }
 }
 
@@ -122,7 +173,7 @@ mod tests {
     async fn test_empty_routes_does_not_panic() {
         let lb = AllLB::new(vec![]);
         
-        lb.process(json!({"update_id": 1})).await;
+        let _ = lb.process(json!({"update_id": 1})).await;
         
         let json = lb.json_struct().await;
         assert_eq!(json["routes"].as_array().unwrap().len(), 0);
@@ -138,7 +189,7 @@ mod tests {
             route2.clone(),
         ]);
 
-        lb.process(json!({"msg": "hello"})).await;
+        let _ = lb.process(json!({"msg": "hello"})).await;
 
         tokio::time::sleep(Duration::from_millis(50)).await;
 
@@ -158,9 +209,9 @@ mod tests {
 
         let lb = AllLB::new(vec![r1.clone(), r2.clone(), r3.clone()]);
 
-        lb.process(json!("m1")).await;
-        lb.process(json!("m2")).await;
-        lb.process(json!("m3")).await;
+        let _ = lb.process(json!("m1")).await;
+        let _ = lb.process(json!("m2")).await;
+        let _ = lb.process(json!("m3")).await;
 
         tokio::time::sleep(Duration::from_millis(50)).await;
 
@@ -169,6 +220,41 @@ mod tests {
         assert_eq!(r3.count().await, 3);
     }
 
+    #[tokio::test]
+    async fn test_result_aggregation() {
+        let r1 = Arc::new(MockCallsRoute::new("1"));
+        let r2 = Arc::new(MockCallsRoute::new("2"));
+        let lb = AllLB::new(vec![r1.clone(), r2.clone()]);
+
+        assert_eq!(lb.process(json!(1)).await, DeliveryResult::Delivered);
+
+        r2.set_failing(true);
+        assert!(matches!(lb.process(json!(2)).await, DeliveryResult::BackendError(_)));
+        assert_eq!(r1.count().await, 2);
+
+        assert!(AllLB::new(vec![]).process(json!(3)).await.is_rejected());
+    }
+
+    #[tokio::test]
+    async fn test_partial_rejection_is_not_offered_to_a_sibling() {
+        use crate::lb::roundrobin::RoundRobinLB;
+
+        let accepting = Arc::new(MockCallsRoute::new("accepting"));
+        let rejecting = Arc::new(MockCallsRoute::new("rejecting"));
+        rejecting.set_result(DeliveryResult::Rejected("full".to_string()));
+        let all = Arc::new(AllLB::new(vec![accepting.clone(), rejecting.clone()]));
+        let sibling = Arc::new(MockCallsRoute::new("sibling"));
+        let lb = RoundRobinLB::new(vec![all, sibling.clone()]);
+
+        let result = lb.process(json!({"update_id": 1})).await;
+        assert!(matches!(result, DeliveryResult::BackendError(_)));
+        assert_eq!(accepting.count().await, 1);
+        assert_eq!(sibling.count().await, 0);
+
+        accepting.set_result(DeliveryResult::Rejected("full".to_string()));
+        assert!(AllLB::new(vec![accepting, rejecting]).process(json!({"update_id": 2})).await.is_rejected());
+    }
+
     #[tokio::test]
     async fn test_json_structure() {
         let r1 = Arc::new(MockCallsRoute::new("x"));
@@ -186,18 +272,18 @@ mod tests {
         let r1 = Arc::new(MockCallsRoute::new("static"));
         let lb = AllLB::new(vec![r1.clone()]);
 
-        lb.process(json!(1)).await;
+        let _ = lb.process(json!(1)).await;
         tokio::time::sleep(Duration::from_millis(20)).await;
         assert_eq!(r1.count().await, 1);
 
         let r2 = Arc::new(MockCallsRoute::new("dynamic"));
-        let add_res = lb.add_route(AddRouteType::Webhook(r2.clone())).await;
+        let add_res = lb.insert_route(None, r2.clone()).await;
         assert!(add_res.is_ok());
 
         let json_out = lb.json_struct().await;
         assert_eq!(json_out["routes"].as_array().unwrap().len(), 2);
 
-        lb.process(json!(2)).await;
+        let _ = lb.process(json!(2)).await;
         tokio::time::sleep(Duration::from_millis(20)).await;
         
         assert_eq!(r1.count().await, 2);
@@ -0,0 +1,277 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
+
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use tokio::sync::RwLock;
+use tokio::time::{Duration, Instant};
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+struct FailoverRoute {
+    route: Arc<dyn RouteableComponent>,
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl FailoverRoute {
+    fn new(route: Arc<dyn RouteableComponent>) -> Self {
+        Self { route, down_until: Mutex::new(None) }
+    }
+
+    fn is_up(&self, now: Instant) -> bool {
+        let down_until = self.down_until.lock().expect("Failover lock poisoned");
+        down_until.is_none_or(|until| now >= until)
+    }
+
+    fn mark(&self, result: &DeliveryResult, cooldown: Duration) {
+        let mut down_until = self.down_until.lock().expect("Failover lock poisoned");
+        *down_until = if result.is_delivered() {
+            None
+        } else {
+            Some(Instant::now() + cooldown)
+        };
+    }
+}
+
+pub struct FailoverLB {
+    routes: RwLock<Vec<Arc<FailoverRoute>>>,
+    cooldown: Duration,
+}
+
+impl FailoverLB {
+    pub fn new(routes: Vec<Arc<dyn RouteableComponent>>, cooldown: Duration) -> Self {
+        Self {
+            routes: RwLock::new(routes.into_iter().map(|r| Arc::new(FailoverRoute::new(r))).collect()),
+            cooldown,
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for FailoverLB {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let routes = self.routes.read().await.clone();
+        let now = Instant::now();
+
+        // Healthy routes in priority order first. Routes that are still cooling down are
+        // tried last, so an update is only lost when every route has failed it. Ejected
+        // routes are not tried at all.
+        let mut up = Vec::new();
+        let mut down = Vec::new();
+        for route in routes.iter() {
+            if !route.route.is_available().await {
+                continue;
+            }
+            if route.is_up(now) {
+                up.push(route);
+            } else {
+                down.push(route);
+            }
+        }
+
+        // A route that failed the update may have dead-lettered it, which only counts once
+        // no other route delivered it.
+        let mut held = HeldDeadLetters::default();
+        let mut result = DeliveryResult::Rejected("no available routes".to_string());
+        for route in up.into_iter().chain(down) {
+            result = held.attempt(route.route.process(update.clone())).await;
+            route.mark(&result, self.cooldown);
+            if result.is_delivered() {
+                break;
+            }
+        }
+        held.settle(&result).await;
+        result
+    }
+
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let mut routes = self.routes.write().await;
+        let index = index.unwrap_or(routes.len());
+        if index > routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        routes.insert(index, Arc::new(FailoverRoute::new(route)));
+        Ok(index)
+    }
+
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let mut routes = self.routes.write().await;
+        if index >= routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        Ok(routes.remove(index).route.clone())
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = self.routes.read().await;
+        routes.get(index).map(|r| r.route.clone())
+    }
+
+    async fn pending(&self) -> usize {
+        let routes = self.routes.read().await;
+        let mut pending = 0;
+        for route in routes.iter() {
+            pending += route.route.pending().await;
+        }
+        pending
+    }
+
+    async fn is_available(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.route.is_available().await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for FailoverLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            router = route.route.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for FailoverLB {
+    async fn print(&self) -> String {
+        let routes = self.routes.read().await;
+        let mut text = String::from("LOAD BALANCER Failover\n\n");
+
+        for (priority, route) in routes.iter().enumerate() {
+            text.push_str(&format!("[priority {}] {}\n\n", priority, route.route.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let routes = self.routes.read().await;
+        let now = Instant::now();
+        let mut routes_json: Vec<Value> = Vec::new();
+        for route in routes.iter() {
+            routes_json.push(json!({
+                "up": route.is_up(now),
+                "route": route.route.json_struct().await
+            }));
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "failover",
+            "cooldown": self.cooldown.as_millis() as u64,
+            "routes": routes_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+
+    #[tokio::test]
+    async fn test_empty_routes_fail() {
+        let lb = FailoverLB::new(vec![], Duration::from_secs(1));
+
+        assert!(!lb.process(json!({"update_id": 1})).await.is_delivered());
+    }
+
+    #[tokio::test]
+    async fn test_primary_receives_everything_while_healthy() {
+        let primary = Arc::new(MockCallsRoute::new("primary"));
+        let standby = Arc::new(MockCallsRoute::new("standby"));
+        let lb = FailoverLB::new(vec![primary.clone(), standby.clone()], Duration::from_secs(1));
+
+        for i in 0..3 {
+            assert!(lb.process(json!(i)).await.is_delivered());
+        }
+
+        assert_eq!(primary.count().await, 3);
+        assert_eq!(standby.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_falls_over_and_back() {
+        let primary = Arc::new(MockCallsRoute::new("primary"));
+        let standby = Arc::new(MockCallsRoute::new("standby"));
+        let lb = FailoverLB::new(vec![primary.clone(), standby.clone()], Duration::from_millis(100));
+
+        primary.set_failing(true);
+        assert!(lb.process(json!(1)).await.is_delivered());
+        assert!(lb.process(json!(2)).await.is_delivered());
+
+        // the first update hit the primary, failed and was handed to the standby;
+        // the second one skipped the primary while it was cooling down
+        assert_eq!(primary.count().await, 1);
+        assert_eq!(standby.get_calls().await, vec![json!(1), json!(2)]);
+        assert_eq!(lb.json_struct().await["routes"][0]["up"], false);
+
+        primary.set_failing(false);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(lb.process(json!(3)).await.is_delivered());
+        assert!(lb.process(json!(4)).await.is_delivered());
+        assert_eq!(primary.count().await, 3);
+        assert_eq!(standby.count().await, 2);
+        assert_eq!(lb.json_struct().await["routes"][0]["up"], true);
+    }
+
+    #[tokio::test]
+    async fn test_ejected_primary_is_not_tried() {
+        let primary = Arc::new(MockCallsRoute::new("primary"));
+        let standby = Arc::new(MockCallsRoute::new("standby"));
+        primary.set_available(false);
+        let lb = FailoverLB::new(vec![primary.clone(), standby.clone()], Duration::from_secs(10));
+
+        assert!(lb.process(json!(1)).await.is_delivered());
+        assert_eq!(primary.count().await, 0);
+        assert_eq!(standby.count().await, 1);
+
+        standby.set_available(false);
+        assert!(lb.process(json!(2)).await.is_rejected());
+    }
+
+    #[tokio::test]
+    async fn test_all_routes_down() {
+        let primary = Arc::new(MockCallsRoute::new("primary"));
+        let standby = Arc::new(MockCallsRoute::new("standby"));
+        primary.set_failing(true);
+        standby.set_failing(true);
+        let lb = FailoverLB::new(vec![primary.clone(), standby.clone()], Duration::from_secs(10));
+
+        assert!(!lb.process(json!(1)).await.is_delivered());
+
+        // cooling down routes are still tried as a last resort
+        standby.set_failing(false);
+        assert!(lb.process(json!(2)).await.is_delivered());
+        assert_eq!(primary.count().await, 2);
+        assert_eq!(standby.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_json_structure() {
+        let lb = FailoverLB::new(vec![Arc::new(MockCallsRoute::new("x"))], Duration::from_millis(500));
+
+        let output = lb.json_struct().await;
+
+        assert_eq!(output["name"], "failover");
+        assert_eq!(output["cooldown"], 500);
+        assert_eq!(output["routes"][0]["route"]["id"], "x");
+    }
+}
@@ -0,0 +1,435 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
+use crate::utils::update::UpdateKey;
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use tokio::sync::RwLock;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+const VIRTUAL_NODES: usize = 160;
+
+/// FNV-1a with the murmur3 finalizer. Unlike `DefaultHasher` it is fixed, so a key lands on
+/// the same route across builds and Rust releases.
+fn hash_of(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+// Points are placed by the name of every route (its url or path) rather than its index, so
+// reordering, inserting or removing routes only moves the keys of the routes that changed.
+// Routes without a name, like nested balancers, are told apart by their order among each other,
+// as are routes sharing a name.
+struct HashRing {
+    routes: Vec<Arc<dyn RouteableComponent>>,
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    fn new(routes: Vec<Arc<dyn RouteableComponent>>) -> Self {
+        let mut ring = Self { routes, points: Vec::new() };
+        ring.place();
+        ring
+    }
+
+    fn insert(&mut self, index: usize, route: Arc<dyn RouteableComponent>) {
+        self.routes.insert(index, route);
+        self.place();
+    }
+
+    fn remove(&mut self, index: usize) -> Arc<dyn RouteableComponent> {
+        let route = self.routes.remove(index);
+        self.place();
+        route
+    }
+
+    fn place(&mut self) {
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        let mut points = Vec::with_capacity(self.routes.len() * VIRTUAL_NODES);
+        for (index, route) in self.routes.iter().enumerate() {
+            let name = route.name().unwrap_or("");
+            let nth = seen.entry(name).or_insert(0);
+            for vnode in 0..VIRTUAL_NODES {
+                points.push((hash_of(format!("{}#{}#{}", name, nth, vnode).as_bytes()), index));
+            }
+            *nth += 1;
+        }
+        points.sort_unstable();
+        self.points = points;
+    }
+
+    // Available routes in the order the ring meets them walking clockwise from the key. The
+    // first one owns the key, so keys of an ejected route spread over the others while every
+    // other key stays where it was.
+    fn order(&self, key: &str, available: &[bool]) -> Vec<usize> {
+        let hash = hash_of(key.as_bytes());
+        let pos = self.points.partition_point(|(point, _)| *point < hash);
+        let wanted = available.iter().filter(|available| **available).count();
+
+        let mut order = Vec::with_capacity(wanted);
+        for i in 0..self.points.len() {
+            if order.len() == wanted {
+                break;
+            }
+            let index = self.points[(pos + i) % self.points.len()].1;
+            if available[index] && !order.contains(&index) {
+                order.push(index);
+            }
+        }
+        order
+    }
+}
+
+pub struct HashLB {
+    ring: RwLock<HashRing>,
+    key: UpdateKey,
+    current: AtomicUsize,
+}
+
+impl HashLB {
+    pub fn new(routes: Vec<Arc<dyn RouteableComponent>>, key: UpdateKey) -> Self {
+        Self {
+            ring: RwLock::new(HashRing::new(routes)),
+            key,
+            current: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for HashLB {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let ring = self.ring.read().await;
+        if ring.routes.is_empty() {
+            return DeliveryResult::Rejected("no routes".to_string());
+        }
+
+        let mut available = Vec::with_capacity(ring.routes.len());
+        for route in ring.routes.iter() {
+            available.push(route.is_available().await);
+        }
+
+        let order = match self.key.extract(&update) {
+            Some(Value::String(key)) => ring.order(key, &available),
+            Some(key) => ring.order(&key.to_string(), &available),
+            None => {
+                let start = self.current.fetch_add(1, Ordering::Relaxed);
+                (0..ring.routes.len())
+                    .map(|i| (start + i) % ring.routes.len())
+                    .filter(|index| available[*index])
+                    .collect()
+            }
+        };
+
+        let candidates: Vec<Arc<dyn RouteableComponent>> = order.into_iter().map(|index| ring.routes[index].clone()).collect();
+
+        drop(ring);
+
+        // A rejected update never reached a backend, so the next route on the ring gets it,
+        // and it is dead-lettered only when none took it.
+        let mut held = HeldDeadLetters::default();
+        let mut result = DeliveryResult::Rejected("no available routes".to_string());
+        for route in candidates {
+            result = held.attempt(route.process(update.clone())).await;
+            if !result.is_rejected() {
+                break;
+            }
+        }
+        held.settle(&result).await;
+        result
+    }
+
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let mut ring = self.ring.write().await;
+        let index = index.unwrap_or(ring.routes.len());
+        if index > ring.routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        ring.insert(index, route);
+        Ok(index)
+    }
+
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let mut ring = self.ring.write().await;
+        if index >= ring.routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        Ok(ring.remove(index))
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let ring = self.ring.read().await;
+        ring.routes.get(index).cloned()
+    }
+
+    async fn pending(&self) -> usize {
+        let ring = self.ring.read().await;
+        let mut pending = 0;
+        for route in ring.routes.iter() {
+            pending += route.pending().await;
+        }
+        pending
+    }
+
+    async fn is_available(&self) -> bool {
+        let ring = self.ring.read().await;
+        for route in ring.routes.iter() {
+            if route.is_available().await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for HashLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let ring = self.ring.read().await;
+        for route in ring.routes.iter() {
+            router = route.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for HashLB {
+    async fn print(&self) -> String {
+        let ring = self.ring.read().await;
+        let mut text = format!("LOAD BALANCER Hash by {}\n\n", self.key.name());
+
+        for route in ring.routes.iter() {
+            text.push_str(&format!("{}\n\n", route.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let ring = self.ring.read().await;
+        let mut routes_json: Vec<Value> = Vec::new();
+        for route in ring.routes.iter() {
+            routes_json.push(route.json_struct().await);
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "hash",
+            "key": self.key.name(),
+            "routes": routes_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+
+    impl HashRing {
+        fn lookup(&self, key: &str, available: &[bool]) -> Option<usize> {
+            self.order(key, available).first().copied()
+        }
+    }
+
+    fn message(chat: i64, user: i64) -> Value {
+        json!({"update_id": 1, "message": {"chat": {"id": chat}, "from": {"id": user}}})
+    }
+
+    #[tokio::test]
+    async fn test_empty_routes_does_not_panic() {
+        let lb = HashLB::new(vec![], UpdateKey::Chat);
+
+        let _ = lb.process(message(1, 1)).await;
+
+        let json = lb.json_struct().await;
+        assert_eq!(json["routes"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_same_chat_sticks_to_one_route() {
+        let routes: Vec<Arc<MockCallsRoute>> = (0..3)
+            .map(|i| Arc::new(MockCallsRoute::new(&i.to_string())))
+            .collect();
+        let lb = HashLB::new(routes.iter().map(|r| r.clone() as Arc<dyn RouteableComponent>).collect(), UpdateKey::Chat);
+
+        for user in 0..20 {
+            let _ = lb.process(message(42, user)).await;
+        }
+
+        let mut counts = Vec::new();
+        for route in &routes {
+            counts.push(route.count().await);
+        }
+        counts.sort();
+        assert_eq!(counts, vec![0, 0, 20]);
+    }
+
+    #[tokio::test]
+    async fn test_user_key_and_fallback() {
+        let r1 = Arc::new(MockCallsRoute::new("1"));
+        let r2 = Arc::new(MockCallsRoute::new("2"));
+        let lb = HashLB::new(vec![r1.clone(), r2.clone()], UpdateKey::User);
+
+        for chat in 0..10 {
+            let _ = lb.process(message(chat, 5)).await;
+        }
+        let (c1, c2) = (r1.count().await, r2.count().await);
+        assert!(c1 == 10 || c2 == 10);
+
+        let _ = lb.process(json!({"update_id": 2, "poll": {"id": "p"}})).await;
+        let _ = lb.process(json!({"update_id": 3, "poll": {"id": "p"}})).await;
+        assert_eq!(r1.count().await + r2.count().await, 12);
+    }
+
+    #[tokio::test]
+    async fn test_add_route_remaps_small_share() {
+        let ring_before = HashRing::new(
+            (0..4).map(|i| Arc::new(MockCallsRoute::new(&i.to_string())) as Arc<dyn RouteableComponent>).collect()
+        );
+        let mut ring_after = HashRing::new(
+            (0..4).map(|i| Arc::new(MockCallsRoute::new(&i.to_string())) as Arc<dyn RouteableComponent>).collect()
+        );
+        ring_after.insert(4, Arc::new(MockCallsRoute::new("4")));
+
+        let mut moved = 0;
+        for chat in 0..10000 {
+            let key = chat.to_string();
+            let before = ring_before.lookup(&key, &[true; 4]);
+            let after = ring_after.lookup(&key, &[true; 5]);
+            if before != after {
+                assert_eq!(after, Some(4));
+                moved += 1;
+            }
+        }
+
+        assert!(moved > 1000 && moved < 3000, "moved {} of 10000", moved);
+    }
+
+    #[tokio::test]
+    async fn test_removed_route_only_moves_its_keys() {
+        let routes: Vec<Arc<dyn RouteableComponent>> = (0..4)
+            .map(|i| Arc::new(MockCallsRoute::new(&i.to_string())) as Arc<dyn RouteableComponent>)
+            .collect();
+        let ring_before = HashRing::new(routes.clone());
+        let mut ring_after = HashRing::new(routes);
+        ring_after.remove(1);
+
+        for chat in 0..10000 {
+            let key = chat.to_string();
+            let before = ring_before.lookup(&key, &[true; 4]).unwrap();
+            let after = ring_after.lookup(&key, &[true; 3]).unwrap();
+            // indexes behind the removed route shift by one
+            if before != 1 {
+                assert_eq!(after, if before > 1 { before - 1 } else { before });
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keys_follow_route_names_not_positions() {
+        let ring_before = HashRing::new(
+            ["a", "b", "c"].iter().map(|id| Arc::new(MockCallsRoute::new(id)) as Arc<dyn RouteableComponent>).collect()
+        );
+        // a new route in front and the rest reordered
+        let ring_after = HashRing::new(
+            ["new", "c", "a", "b"].iter().map(|id| Arc::new(MockCallsRoute::new(id)) as Arc<dyn RouteableComponent>).collect()
+        );
+
+        for chat in 0..10000 {
+            let key = chat.to_string();
+            let before = ring_before.routes[ring_before.lookup(&key, &[true; 3]).unwrap()].name();
+            let after = ring_after.routes[ring_after.lookup(&key, &[true; 4]).unwrap()].name();
+            if after != Some("new") {
+                assert_eq!(before, after);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_is_fixed() {
+        // a changed value moves every chat to another route
+        assert_eq!(hash_of(b""), 0xefd01f60ba992926);
+        assert_eq!(hash_of(b"42"), 0x810b196a56ee3cec);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_update_goes_to_next_ring_position() {
+        let routes: Vec<Arc<MockCallsRoute>> = (0..3)
+            .map(|i| Arc::new(MockCallsRoute::new(&i.to_string())))
+            .collect();
+        let lb = HashLB::new(routes.iter().map(|r| r.clone() as Arc<dyn RouteableComponent>).collect(), UpdateKey::Chat);
+
+        let owner = lb.ring.read().await.lookup("42", &[true; 3]).unwrap();
+        let next = lb.ring.read().await.order("42", &[true; 3])[1];
+        routes[owner].set_result(DeliveryResult::Rejected("full".to_string()));
+
+        // the chat moves to the next route on the ring as long as its own one rejects it
+        assert!(lb.process(message(42, 1)).await.is_delivered());
+        assert_eq!(routes[owner].count().await, 1);
+        assert_eq!(routes[next].count().await, 1);
+
+        for route in &routes {
+            route.set_result(DeliveryResult::Rejected("full".to_string()));
+        }
+        assert!(lb.process(message(42, 2)).await.is_rejected());
+        let mut calls = 0;
+        for route in &routes {
+            calls += route.count().await;
+        }
+        assert_eq!(calls, 5);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_route_keys_move_elsewhere() {
+        let ring = HashRing::new(
+            (0..4).map(|i| Arc::new(MockCallsRoute::new(&i.to_string())) as Arc<dyn RouteableComponent>).collect()
+        );
+        let available = [true, false, true, true];
+
+        for chat in 0..10000 {
+            let key = chat.to_string();
+            let before = ring.lookup(&key, &[true; 4]).unwrap();
+            let after = ring.lookup(&key, &available).unwrap();
+            if before == 1 {
+                assert_ne!(after, 1);
+            } else {
+                assert_eq!(before, after);
+            }
+        }
+
+        assert_eq!(ring.lookup("1", &[false; 4]), None);
+    }
+
+    #[tokio::test]
+    async fn test_json_structure() {
+        let lb = HashLB::new(vec![Arc::new(MockCallsRoute::new("x"))], UpdateKey::path("message.chat.id"));
+
+        let output = lb.json_struct().await;
+
+        assert_eq!(output["type"], "load-balancer");
+        assert_eq!(output["name"], "hash");
+        assert_eq!(output["key"], "message.chat.id");
+        assert_eq!(output["routes"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use tokio::sync::RwLock;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rand::Rng;
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+pub enum LeastPendingMode {
+    Least,
+    PowerOfTwoChoices,
+}
+
+pub struct LeastPendingLB {
+    routes: RwLock<Vec<Arc<dyn RouteableComponent>>>,
+    mode: LeastPendingMode,
+    current: AtomicUsize,
+}
+
+impl LeastPendingLB {
+    pub fn new(routes: Vec<Arc<dyn RouteableComponent>>, mode: LeastPendingMode) -> Self {
+        Self {
+            routes: RwLock::new(routes),
+            mode,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    async fn least(&self, routes: &[Arc<dyn RouteableComponent>]) -> usize {
+        // Start the scan at a rotating offset so that idle routes share ties evenly.
+        let start = self.current.fetch_add(1, Ordering::Relaxed);
+
+        let mut best = start % routes.len();
+        let mut best_pending = usize::MAX;
+
+        for i in 0..routes.len() {
+            let index = (start + i) % routes.len();
+            let pending = routes[index].pending().await;
+            if pending < best_pending {
+                best = index;
+                best_pending = pending;
+            }
+        }
+        best
+    }
+
+    async fn power_of_two(&self, routes: &[Arc<dyn RouteableComponent>]) -> usize {
+        if routes.len() == 1 {
+            return 0;
+        }
+
+        let (a, b) = {
+            let mut rng = rand::rng();
+            let a = rng.random_range(0..routes.len());
+            let b = (a + rng.random_range(1..routes.len())) % routes.len();
+            (a, b)
+        };
+
+        if routes[b].pending().await < routes[a].pending().await {
+            b
+        } else {
+            a
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            LeastPendingMode::Least => "least",
+            LeastPendingMode::PowerOfTwoChoices => "power-of-two-choices",
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for LeastPendingLB {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let routes = self.routes.read().await.clone();
+        if routes.is_empty() {
+            return DeliveryResult::Rejected("no routes".to_string());
+        }
+
+        let mut available = Vec::with_capacity(routes.len());
+        for route in routes {
+            if route.is_available().await {
+                available.push(route);
+            }
+        }
+
+        // A rejected update never reached a backend, so the next least pending route gets it,
+        // and it is dead-lettered only when none took it.
+        let mut held = HeldDeadLetters::default();
+        let mut result = DeliveryResult::Rejected("no available routes".to_string());
+        while !available.is_empty() {
+            let index = match self.mode {
+                LeastPendingMode::Least => self.least(&available).await,
+                LeastPendingMode::PowerOfTwoChoices => self.power_of_two(&available).await,
+            };
+
+            let route = available.remove(index);
+            result = held.attempt(route.process(update.clone())).await;
+            if !result.is_rejected() {
+                break;
+            }
+        }
+        held.settle(&result).await;
+        result
+    }
+
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let mut routes = self.routes.write().await;
+        let index = index.unwrap_or(routes.len());
+        if index > routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        routes.insert(index, route);
+        Ok(index)
+    }
+
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let mut routes = self.routes.write().await;
+        if index >= routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        Ok(routes.remove(index))
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = self.routes.read().await;
+        routes.get(index).cloned()
+    }
+
+    async fn pending(&self) -> usize {
+        let routes = self.routes.read().await;
+        let mut pending = 0;
+        for route in routes.iter() {
+            pending += route.pending().await;
+        }
+        pending
+    }
+    async fn is_available(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.is_available().await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for LeastPendingLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            router = route.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for LeastPendingLB {
+    async fn print(&self) -> String {
+        let routes = self.routes.read().await;
+        let mut text = format!("LOAD BALANCER LeastPending ({})\n\n", self.mode_name());
+
+        for route in routes.iter() {
+            text.push_str(&format!("{}\n\n", route.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let routes = self.routes.read().await;
+        let mut routes_json: Vec<Value> = Vec::new();
+        for route in routes.iter() {
+            routes_json.push(route.json_struct().await);
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "least-pending",
+            "mode": self.mode_name(),
+            "routes": routes_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+
+    #[tokio::test]
+    async fn test_empty_routes_does_not_panic() {
+        let lb = LeastPendingLB::new(vec![], LeastPendingMode::PowerOfTwoChoices);
+
+        let _ = lb.process(json!({"update_id": 1})).await;
+
+        let json = lb.json_struct().await;
+        assert_eq!(json["routes"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_least_avoids_busy_route() {
+        let busy = Arc::new(MockCallsRoute::new("busy"));
+        let idle1 = Arc::new(MockCallsRoute::new("idle1"));
+        let idle2 = Arc::new(MockCallsRoute::new("idle2"));
+        busy.set_pending(50);
+
+        let lb = LeastPendingLB::new(vec![busy.clone(), idle1.clone(), idle2.clone()], LeastPendingMode::Least);
+
+        for i in 0..10 {
+            let _ = lb.process(json!(i)).await;
+        }
+
+        assert_eq!(busy.count().await, 0);
+        assert!(idle1.count().await > 0);
+        assert!(idle2.count().await > 0);
+        assert_eq!(idle1.count().await + idle2.count().await, 10);
+        assert_eq!(lb.pending().await, 50);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_route_is_skipped() {
+        let down = Arc::new(MockCallsRoute::new("down"));
+        let busy = Arc::new(MockCallsRoute::new("busy"));
+        down.set_available(false);
+        busy.set_pending(10);
+
+        let lb = LeastPendingLB::new(vec![down.clone(), busy.clone()], LeastPendingMode::Least);
+
+        for i in 0..5 {
+            assert!(lb.process(json!(i)).await.is_delivered());
+        }
+        assert_eq!(down.count().await, 0);
+        assert_eq!(busy.count().await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_update_goes_to_next_least_pending() {
+        let full = Arc::new(MockCallsRoute::new("full"));
+        let some = Arc::new(MockCallsRoute::new("some"));
+        let busy = Arc::new(MockCallsRoute::new("busy"));
+        full.set_result(DeliveryResult::Rejected("full".to_string()));
+        some.set_pending(3);
+        busy.set_pending(10);
+
+        let lb = LeastPendingLB::new(vec![full.clone(), some.clone(), busy.clone()], LeastPendingMode::Least);
+
+        assert!(lb.process(json!(1)).await.is_delivered());
+        assert_eq!(full.count().await, 1);
+        assert_eq!(some.get_calls().await, vec![json!(1)]);
+        assert_eq!(busy.count().await, 0);
+
+        some.set_result(DeliveryResult::Rejected("full".to_string()));
+        busy.set_result(DeliveryResult::Rejected("full".to_string()));
+        assert!(lb.process(json!(2)).await.is_rejected());
+        assert_eq!(busy.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_power_of_two_never_picks_the_busiest() {
+        let busy = Arc::new(MockCallsRoute::new("busy"));
+        let idle = Arc::new(MockCallsRoute::new("idle"));
+        busy.set_pending(10);
+
+        let lb = LeastPendingLB::new(vec![busy.clone(), idle.clone()], LeastPendingMode::PowerOfTwoChoices);
+
+        for i in 0..20 {
+            let _ = lb.process(json!(i)).await;
+        }
+
+        assert_eq!(busy.count().await, 0);
+        assert_eq!(idle.count().await, 20);
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_add_route_and_json() {
+        let r1 = Arc::new(MockCallsRoute::new("static"));
+        r1.set_pending(1);
+        let lb = LeastPendingLB::new(vec![r1.clone()], LeastPendingMode::Least);
+
+        let r2 = Arc::new(MockCallsRoute::new("dynamic"));
+        assert!(lb.insert_route(None, r2.clone()).await.is_ok());
+
+        let _ = lb.process(json!(1)).await;
+        assert_eq!(r2.count().await, 1);
+
+        let output = lb.json_struct().await;
+        assert_eq!(output["name"], "least-pending");
+        assert_eq!(output["mode"], "least");
+        assert_eq!(output["routes"].as_array().unwrap().len(), 2);
+    }
+}
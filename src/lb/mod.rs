@@ -1,3 +1,7 @@
 
 pub mod roundrobin;
-pub mod all;
\ No newline at end of file
+pub mod all;
+pub mod hash;
+pub mod weighted;
+pub mod leastpending;
+pub mod failover;
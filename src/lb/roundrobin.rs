@@ -1,7 +1,6 @@
 
-use crate::base::{Routeable, RouteableComponent, Serverable, Printable};
-
-use crate::api::message::AddRouteType;
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
 
 use tokio::sync::mpsc::Sender;
 use axum::{Router};
@@ -11,8 +10,6 @@ use tokio::sync::RwLock;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
-
 use async_trait::async_trait;
 
 use serde_json::{Value, json};
@@ -33,43 +30,74 @@ impl RoundRobinLB {
 
 #[async_trait]
 impl Routeable for RoundRobinLB {
-    async fn process(&self, update: Value) {
+    async fn process(&self, update: Value) -> DeliveryResult {
         let routes = self.routes.read().await;
         if routes.is_empty() {
-            return;
+            return DeliveryResult::Rejected("no routes".to_string());
         }
         let current = self.current.fetch_add(1, Ordering::Relaxed);
-        let index = current % routes.len();
-
-        let route = routes[index].clone();
+        let candidates: Vec<Arc<dyn RouteableComponent>> = (0..routes.len())
+            .map(|i| routes[(current + i) % routes.len()].clone())
+            .collect();
 
         drop(routes); 
 
-        route.process(update).await;
+        // Ejected routes are skipped. A rejected update never reached a backend, so it is
+        // offered to the next route, and dead-lettered only when none took it.
+        let mut held = HeldDeadLetters::default();
+        let mut result = DeliveryResult::Rejected("no available routes".to_string());
+        for route in candidates {
+            if !route.is_available().await {
+                continue;
+            }
+            result = held.attempt(route.process(update.clone())).await;
+            if !result.is_rejected() {
+                break;
+            }
+        }
+        held.settle(&result).await;
+        result
+    }
 
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let mut routes = self.routes.write().await;
+        let index = index.unwrap_or(routes.len());
+        if index > routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        routes.insert(index, route);
+        Ok(index)
     }
 
-    async fn add_route(&self, route: AddRouteType) -> Result<(), ()>{
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
         let mut routes = self.routes.write().await;
+        if index >= routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        Ok(routes.remove(index))
+    }
 
-        match route {
-            AddRouteType::Longpull(route_arc) => {
-                match LONGPOLL_REGISTRY.write() {
-                    Ok(mut registry) => {
-                        registry.insert(route_arc.path.clone(), route_arc.clone());
-                        routes.push(route_arc); 
-                        Ok(())
-                    }
-                    Err(_) => {
-                        Err(())
-                    }
-                }
-            },
-            AddRouteType::Webhook(route) => {
-                routes.push(route); 
-                Ok(())
-            },
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = self.routes.read().await;
+        routes.get(index).cloned()
+    }
+
+    async fn pending(&self) -> usize {
+        let routes = self.routes.read().await;
+        let mut pending = 0;
+        for route in routes.iter() {
+            pending += route.pending().await;
+        }
+        pending
+    }
+    async fn is_available(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.is_available().await {
+                return true;
+            }
         }
+        false
     }
 }
 
@@ -124,7 +152,7 @@ mod tests {
     async fn test_empty_routes_does_not_panic() {
         let lb = RoundRobinLB::new(vec![]);
         
-        lb.process(json!({"update_id": 1})).await;
+        let _ = lb.process(json!({"update_id": 1})).await;
         
         let json = lb.json_struct().await;
         assert_eq!(json["routes"].as_array().unwrap().len(), 0);
@@ -141,7 +169,7 @@ mod tests {
         ]);
 
         for i in 0..4 {
-            lb.process(json!({"id": i})).await;
+            let _ = lb.process(json!({"id": i})).await;
         }
 
         assert_eq!(route1.count().await, 2);
@@ -157,23 +185,64 @@ mod tests {
 
         let lb = RoundRobinLB::new(vec![r1.clone(), r2.clone(), r3.clone()]);
 
-        lb.process(json!("msg1")).await;
+        let _ = lb.process(json!("msg1")).await;
         let c1 = r1.get_calls().await;
         assert_eq!(c1.len(), 1);
         assert_eq!(c1[0], "msg1");
         assert_eq!(r2.count().await, 0);
         assert_eq!(r3.count().await, 0);
 
-        lb.process(json!("msg2")).await;
+        let _ = lb.process(json!("msg2")).await;
         assert_eq!(r2.count().await, 1);
 
-        lb.process(json!("msg3")).await;
+        let _ = lb.process(json!("msg3")).await;
         assert_eq!(r3.count().await, 1);
 
-        lb.process(json!("msg4")).await;
+        let _ = lb.process(json!("msg4")).await;
         assert_eq!(r1.count().await, 2);
     }
 
+    #[tokio::test]
+    async fn test_rejected_update_goes_to_next_route() {
+        let r1 = Arc::new(MockCallsRoute::new("1"));
+        let r2 = Arc::new(MockCallsRoute::new("2"));
+        let r3 = Arc::new(MockCallsRoute::new("3"));
+        r1.set_result(DeliveryResult::Rejected("full".to_string()));
+
+        let lb = RoundRobinLB::new(vec![r1.clone(), r2.clone(), r3.clone()]);
+
+        assert!(lb.process(json!("msg1")).await.is_delivered());
+        assert_eq!(r2.get_calls().await, vec![json!("msg1")]);
+
+        r2.set_failing(true);
+        assert!(matches!(lb.process(json!("msg2")).await, DeliveryResult::BackendError(_)));
+        assert_eq!(r3.count().await, 0);
+
+        r2.set_result(DeliveryResult::Rejected("full".to_string()));
+        r3.set_result(DeliveryResult::Rejected("full".to_string()));
+        assert!(lb.process(json!("msg3")).await.is_rejected());
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_route_is_skipped() {
+        let r1 = Arc::new(MockCallsRoute::new("1"));
+        let r2 = Arc::new(MockCallsRoute::new("2"));
+        r2.set_available(false);
+
+        let lb = RoundRobinLB::new(vec![r1.clone(), r2.clone()]);
+
+        for i in 0..4 {
+            assert!(lb.process(json!(i)).await.is_delivered());
+        }
+        assert_eq!(r1.count().await, 4);
+        assert_eq!(r2.count().await, 0);
+        assert!(lb.is_available().await);
+
+        r1.set_available(false);
+        assert!(!lb.is_available().await);
+        assert!(lb.process(json!(5)).await.is_rejected());
+    }
+
     #[tokio::test]
     async fn test_json_structure_aggregation() {
         let r1 = Arc::new(MockCallsRoute::new("alpha"));
@@ -197,18 +266,18 @@ mod tests {
         let r1 = Arc::new(MockCallsRoute::new("static"));
         let lb = RoundRobinLB::new(vec![r1.clone()]);
 
-        lb.process(json!(1)).await;
+        let _ = lb.process(json!(1)).await;
         assert_eq!(r1.count().await, 1);
 
         let r2 = Arc::new(MockCallsRoute::new("dynamic"));
         
-        let add_res = lb.add_route(AddRouteType::Webhook(r2.clone())).await;
+        let add_res = lb.insert_route(None, r2.clone()).await;
         assert!(add_res.is_ok());
 
         let json_out = lb.json_struct().await;
         assert_eq!(json_out["routes"].as_array().unwrap().len(), 2);
 
-        lb.process(json!(2)).await; 
+        let _ = lb.process(json!(2)).await; 
         
         assert_eq!(r2.count().await, 1);
     }
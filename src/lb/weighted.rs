@@ -0,0 +1,331 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult, RouteError};
+use crate::route::deadletter::HeldDeadLetters;
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+struct WeightedRoute {
+    route: Arc<dyn RouteableComponent>,
+    weight: u32,
+    current: i64,
+}
+
+pub struct WeightedRoundRobinLB {
+    routes: Mutex<Vec<WeightedRoute>>,
+}
+
+impl WeightedRoundRobinLB {
+    pub fn new(routes: Vec<(Arc<dyn RouteableComponent>, u32)>) -> Self {
+        let routes = routes
+            .into_iter()
+            .map(|(route, weight)| WeightedRoute { route, weight, current: 0 })
+            .collect();
+
+        Self {
+            routes: Mutex::new(routes),
+        }
+    }
+
+    fn snapshot(&self) -> Vec<(Arc<dyn RouteableComponent>, u32)> {
+        let routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        routes.iter().map(|r| (r.route.clone(), r.weight)).collect()
+    }
+
+    // Smooth weighted round-robin (as in nginx): every pick raises each route by its weight,
+    // takes the highest one and lowers it by the total, so heavy routes are interleaved.
+    // Routes that are unavailable (or were added after `available` was taken) sit the round out.
+    fn next(&self, available: &[bool]) -> Option<(usize, Arc<dyn RouteableComponent>)> {
+        let mut routes = self.routes.lock().expect("Weighted routes lock poisoned");
+
+        let mut total: i64 = 0;
+        let mut best: Option<(usize, i64)> = None;
+
+        for (i, route) in routes.iter_mut().enumerate() {
+            if route.weight == 0 || !available.get(i).copied().unwrap_or(false) {
+                continue;
+            }
+            route.current += route.weight as i64;
+            total += route.weight as i64;
+
+            if best.is_none_or(|(_, current)| route.current > current) {
+                best = Some((i, route.current));
+            }
+        }
+
+        let (best, _) = best?;
+        routes[best].current -= total;
+        Some((best, routes[best].route.clone()))
+    }
+}
+
+#[async_trait]
+impl Routeable for WeightedRoundRobinLB {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let mut available = Vec::new();
+        for (route, _) in self.snapshot() {
+            available.push(route.is_available().await);
+        }
+
+        // A rejected update never reached a backend, so the next route in weighted order
+        // gets it, and it is dead-lettered only when none took it.
+        let mut held = HeldDeadLetters::default();
+        let mut result = DeliveryResult::Rejected("no available routes with a positive weight".to_string());
+        while let Some((index, route)) = self.next(&available) {
+            result = held.attempt(route.process(update.clone())).await;
+            if !result.is_rejected() {
+                break;
+            }
+            available[index] = false;
+        }
+        held.settle(&result).await;
+        result
+    }
+
+    async fn insert_route(&self, index: Option<usize>, route: Arc<dyn RouteableComponent>) -> Result<usize, RouteError> {
+        let mut routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        let index = index.unwrap_or(routes.len());
+        if index > routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        routes.insert(index, WeightedRoute { route, weight: 1, current: 0 });
+        Ok(index)
+    }
+
+    async fn remove_route(&self, index: usize) -> Result<Arc<dyn RouteableComponent>, RouteError> {
+        let mut routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        if index >= routes.len() {
+            return Err(RouteError::NotFound);
+        }
+        let removed = routes.remove(index);
+
+        for route in routes.iter_mut() {
+            route.current = 0;
+        }
+        Ok(removed.route)
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        routes.get(index).map(|r| r.route.clone())
+    }
+
+    async fn set_weight(&self, index: usize, weight: u32) -> Result<(), RouteError> {
+        let mut routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        let route = routes.get_mut(index).ok_or(RouteError::NotFound)?;
+        route.weight = weight;
+
+        for route in routes.iter_mut() {
+            route.current = 0;
+        }
+        Ok(())
+    }
+
+    async fn weight(&self, index: usize) -> Option<u32> {
+        let routes = self.routes.lock().expect("Weighted routes lock poisoned");
+        routes.get(index).map(|r| r.weight)
+    }
+
+    async fn pending(&self) -> usize {
+        let mut pending = 0;
+        for (route, _) in self.snapshot() {
+            pending += route.pending().await;
+        }
+        pending
+    }
+
+    async fn is_available(&self) -> bool {
+        for (route, weight) in self.snapshot() {
+            if weight > 0 && route.is_available().await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for WeightedRoundRobinLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        for (route, _) in self.snapshot() {
+            router = route.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for WeightedRoundRobinLB {
+    async fn print(&self) -> String {
+        let mut text = String::from("LOAD BALANCER WeightedRoundRobin\n\n");
+
+        for (route, weight) in self.snapshot() {
+            text.push_str(&format!("[weight {}] {}\n\n", weight, route.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let mut routes_json: Vec<Value> = Vec::new();
+        for (route, weight) in self.snapshot() {
+            routes_json.push(json!({
+                "weight": weight,
+                "route": route.json_struct().await
+            }));
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "weighted-round-robin",
+            "routes": routes_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+
+    async fn picks(lb: &WeightedRoundRobinLB, routes: &[Arc<MockCallsRoute>], n: usize) -> String {
+        let mut order = String::new();
+        for i in 0..n {
+            let mut before = Vec::new();
+            for route in routes {
+                before.push(route.count().await);
+            }
+
+            let _ = lb.process(json!(i)).await;
+
+            for (route, count) in routes.iter().zip(before) {
+                if route.count().await > count {
+                    order.push_str(&route.id);
+                }
+            }
+        }
+        order
+    }
+
+    #[tokio::test]
+    async fn test_empty_routes_does_not_panic() {
+        let lb = WeightedRoundRobinLB::new(vec![]);
+
+        let _ = lb.process(json!({"update_id": 1})).await;
+
+        let json = lb.json_struct().await;
+        assert_eq!(json["routes"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_smooth_distribution() {
+        let routes = vec![
+            Arc::new(MockCallsRoute::new("a")),
+            Arc::new(MockCallsRoute::new("b")),
+            Arc::new(MockCallsRoute::new("c")),
+        ];
+        let lb = WeightedRoundRobinLB::new(vec![
+            (routes[0].clone(), 5),
+            (routes[1].clone(), 1),
+            (routes[2].clone(), 1),
+        ]);
+
+        assert_eq!(picks(&lb, &routes, 14).await, "aabacaaaabacaa");
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_route_sits_out() {
+        let routes = vec![
+            Arc::new(MockCallsRoute::new("a")),
+            Arc::new(MockCallsRoute::new("b")),
+        ];
+        let lb = WeightedRoundRobinLB::new(vec![
+            (routes[0].clone(), 3),
+            (routes[1].clone(), 1),
+        ]);
+
+        routes[0].set_available(false);
+        assert_eq!(picks(&lb, &routes, 4).await, "bbbb");
+
+        routes[1].set_available(false);
+        assert!(lb.process(json!(0)).await.is_rejected());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_update_goes_to_next_route() {
+        let routes = [
+            Arc::new(MockCallsRoute::new("a")),
+            Arc::new(MockCallsRoute::new("b")),
+            Arc::new(MockCallsRoute::new("c")),
+        ];
+        let lb = WeightedRoundRobinLB::new(vec![
+            (routes[0].clone(), 5),
+            (routes[1].clone(), 1),
+            (routes[2].clone(), 1),
+        ]);
+        routes[0].set_result(DeliveryResult::Rejected("full".to_string()));
+
+        assert!(lb.process(json!(1)).await.is_delivered());
+        assert_eq!(routes[0].count().await, 1);
+        assert_eq!(routes[1].count().await + routes[2].count().await, 1);
+
+        routes[1].set_failing(true);
+        routes[2].set_failing(true);
+        assert!(matches!(lb.process(json!(2)).await, DeliveryResult::BackendError(_)));
+        assert_eq!(routes[1].count().await + routes[2].count().await, 2);
+
+        routes[1].set_result(DeliveryResult::Rejected("full".to_string()));
+        routes[2].set_result(DeliveryResult::Rejected("full".to_string()));
+        assert!(lb.process(json!(3)).await.is_rejected());
+        assert_eq!(routes[0].count().await + routes[1].count().await + routes[2].count().await, 7);
+    }
+
+    #[tokio::test]
+    async fn test_set_weight_at_runtime() {
+        let routes = vec![
+            Arc::new(MockCallsRoute::new("a")),
+            Arc::new(MockCallsRoute::new("b")),
+        ];
+        let lb = WeightedRoundRobinLB::new(vec![
+            (routes[0].clone(), 1),
+            (routes[1].clone(), 1),
+        ]);
+
+        assert!(lb.set_weight(1, 3).await.is_ok());
+        assert_eq!(picks(&lb, &routes, 4).await, "babb");
+
+        assert!(lb.set_weight(0, 0).await.is_ok());
+        assert_eq!(picks(&lb, &routes, 3).await, "bbb");
+
+        assert!(lb.set_weight(5, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_add_route_and_json() {
+        let r1 = Arc::new(MockCallsRoute::new("static"));
+        let lb = WeightedRoundRobinLB::new(vec![(r1.clone(), 2)]);
+
+        let r2 = Arc::new(MockCallsRoute::new("dynamic"));
+        assert!(lb.insert_route(None, r2.clone()).await.is_ok());
+
+        for i in 0..3 {
+            let _ = lb.process(json!(i)).await;
+        }
+        assert_eq!(r1.count().await, 2);
+        assert_eq!(r2.count().await, 1);
+
+        let output = lb.json_struct().await;
+        assert_eq!(output["name"], "weighted-round-robin");
+        assert_eq!(output["routes"][0]["weight"], 2);
+        assert_eq!(output["routes"][1]["weight"], 1);
+        assert_eq!(output["routes"][1]["route"]["id"], "dynamic");
+    }
+}
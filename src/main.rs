@@ -1,19 +1,28 @@
 mod base;
 mod lb;
+mod router;
 mod route;
 mod tgin;
 mod update;
 mod config;
 mod utils;
 mod dynamic;
+mod stats;
+mod dedup;
+mod ordering;
+mod shutdown;
 
 mod api;
 
 use crate::tgin::Tgin;
-use crate::config::setup::{load_config, build_updates, build_route};
+use crate::dedup::Dedup;
+use crate::config::setup::{load_config, build_updates, build_update_key, check_longpoll_bots, RouteBuilder};
+use crate::config::reload::{Reloader, StartupSettings};
 
 use clap::{Arg, Command};
 
+use std::time::Duration;
+
 #[cfg(test)]
 mod mock;
 
@@ -37,8 +46,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 
     let conf = load_config(config_path); 
-    let inputs = build_updates(conf.updates);
-    let lb = build_route(conf.route);
+    let settings = StartupSettings::of(&conf);
+    let reload_updates = conf.updates.clone();
+    let inputs = build_updates(conf.updates, conf.ssl.as_ref()).unwrap_or_else(|err| panic!("{}", err));
+    let mut routes = RouteBuilder::default();
+    let lb = routes.build(conf.route).unwrap_or_else(|err| panic!("{}", err));
+    check_longpoll_bots(&reload_updates, &routes).unwrap_or_else(|err| panic!("{}", err));
 
     let mut tgin = Tgin::new(
         inputs,
@@ -52,6 +65,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         tgin.set_api(api);
     }
 
+    if let Some(dedup) = conf.dedup {
+        tgin.set_dedup(Dedup::new(Duration::from_millis(dedup.ttl), dedup.max_entries));
+    }
+
+    tgin.set_shutdown(
+        Duration::from_millis(conf.shutdown.timeout),
+        Duration::from_millis(conf.shutdown.drain_window),
+    );
+
+    tgin.set_max_in_flight(conf.max_in_flight);
+
+    if let Some(key) = conf.ordered_by {
+        tgin.set_ordered_by(build_update_key(key));
+    }
+
+    tgin.set_reloader(Reloader::new(config_path.to_string(), settings, reload_updates, routes));
+
     if let Some(ssl) = conf.ssl {
         tgin.set_ssl(ssl.cert, ssl.key);
     }
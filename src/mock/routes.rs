@@ -2,24 +2,58 @@
 
 use async_trait::async_trait;
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{RwLock, Mutex};
-use crate::base::{Routeable, RouteableComponent, Printable, Serverable};
-use tokio::sync::mpsc::Sender;
-use axum::Router;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::Mutex;
+use crate::base::{Routeable, Printable, Serverable, DeliveryResult};
 
 pub struct MockCallsRoute {
     pub id: String,
     pub calls: Arc<Mutex<Vec<Value>>>,
+    pub pending: AtomicUsize,
+    pub available: AtomicBool,
+    pub result: std::sync::Mutex<DeliveryResult>,
+    pub delays: std::sync::Mutex<HashMap<i64, Duration>>,
 }
 
 impl MockCallsRoute {
     pub fn new(id: &str) -> Self {
         Self { 
             id: id.to_string(), 
-            calls: Arc::new(Mutex::new(vec![])) 
+            calls: Arc::new(Mutex::new(vec![])),
+            pending: AtomicUsize::new(0),
+            available: AtomicBool::new(true),
+            result: std::sync::Mutex::new(DeliveryResult::Delivered),
+            delays: std::sync::Mutex::new(HashMap::new()),
         }
     }
+
+    pub fn set_result(&self, result: DeliveryResult) {
+        *self.result.lock().unwrap() = result;
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        self.set_result(if failing {
+            DeliveryResult::BackendError(format!("{} is failing", self.id))
+        } else {
+            DeliveryResult::Delivered
+        });
+    }
+
+    pub fn set_available(&self, available: bool) {
+        self.available.store(available, Ordering::Relaxed);
+    }
+
+    /// Makes processing of the update with `update_id` take `delay` after it was recorded.
+    pub fn set_delay(&self, update_id: i64, delay: Duration) {
+        self.delays.lock().unwrap().insert(update_id, delay);
+    }
+
+    pub fn set_pending(&self, pending: usize) {
+        self.pending.store(pending, Ordering::Relaxed);
+    }
     
     pub async fn count(&self) -> usize {
         self.calls.lock().await.len()
@@ -32,8 +66,25 @@ impl MockCallsRoute {
 
 #[async_trait]
 impl Routeable for MockCallsRoute {
-    async fn process(&self, update: Value) {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let delay = update["update_id"].as_i64().and_then(|id| self.delays.lock().unwrap().get(&id).copied());
         self.calls.lock().await.push(update);
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        self.result.lock().unwrap().clone()
+    }
+
+    async fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    async fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.id)
     }
 }
 
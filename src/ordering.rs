@@ -0,0 +1,180 @@
+use crate::base::RouteableComponent;
+use crate::stats::DeliveryStats;
+use crate::shutdown::{InFlight, InFlightGuard};
+use crate::utils::update::UpdateKey;
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+
+/// Every update carries the tree it was dispatched to, a reload swaps the tree without reordering,
+/// and its delivery slot, so queued updates count against `max_in_flight` like running ones.
+type Item = (Arc<dyn RouteableComponent>, OwnedSemaphorePermit, Value);
+type Queues = Arc<Mutex<HashMap<String, UnboundedSender<Item>>>>;
+
+/// Ordered delivery: updates with the same key (a chat by default) go through the route tree one
+/// after another in the order they arrived, updates with different keys are processed in parallel.
+///
+/// Every key with updates in flight has a queue drained by its own task, the task ends and the
+/// queue is dropped once it runs empty. The queues are not bounded one by one, together they hold
+/// no more updates than there are delivery slots. Updates without a key are not ordered.
+pub struct Lanes {
+    key: UpdateKey,
+    stats: Arc<DeliveryStats>,
+    in_flight: Arc<InFlight>,
+    queues: Queues,
+}
+
+impl Lanes {
+    pub fn new(
+        key: UpdateKey,
+        stats: Arc<DeliveryStats>,
+        in_flight: Arc<InFlight>,
+    ) -> Self {
+        Self { key, stats, in_flight, queues: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Queues the update behind earlier updates with the same key. Must be called in arrival order,
+    /// so it does not wait for anything. `permit` is released once the update is processed.
+    pub fn push(&self, route: Arc<dyn RouteableComponent>, permit: OwnedSemaphorePermit, update: Value) {
+        let Some(key) = self.key.extract(&update).map(Value::to_string) else {
+            let stats = self.stats.clone();
+            let guard = self.in_flight.track();
+            tokio::spawn(async move {
+                stats.record(&route.process(update).await);
+                drop(permit);
+                drop(guard);
+            });
+            return;
+        };
+
+        let mut queues = self.queues.lock().expect("Lanes lock poisoned");
+        let item = match queues.get(&key) {
+            Some(queue) => match queue.send((route, permit, update)) {
+                Ok(()) => return,
+                Err(err) => err.0,
+            },
+            None => (route, permit, update),
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = tx.send(item);
+        queues.insert(key.clone(), tx);
+        drop(queues);
+
+        // The queue counts as in flight until it runs empty.
+        let guard = self.in_flight.track();
+        tokio::spawn(drain(key, rx, self.stats.clone(), self.queues.clone(), guard));
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.queues.lock().expect("Lanes lock poisoned").len()
+    }
+}
+
+async fn drain(
+    key: String,
+    mut rx: UnboundedReceiver<Item>,
+    stats: Arc<DeliveryStats>,
+    queues: Queues,
+    _guard: InFlightGuard,
+) {
+    loop {
+        let (route, permit, update) = match rx.try_recv() {
+            Ok(item) => item,
+            Err(_) => {
+                // Checked again under the lock, so nothing can be queued between the check and the removal.
+                let mut queues = queues.lock().expect("Lanes lock poisoned");
+                match rx.try_recv() {
+                    Ok(item) => item,
+                    Err(_) => {
+                        queues.remove(&key);
+                        return;
+                    }
+                }
+            }
+        };
+        stats.record(&route.process(update).await);
+        drop(permit);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+    use serde_json::json;
+    use tokio::sync::Semaphore;
+
+    use std::time::Duration;
+
+    fn message(update_id: i64, chat: i64) -> Value {
+        json!({"update_id": update_id, "message": {"chat": {"id": chat}, "text": "hi"}})
+    }
+
+    fn permit() -> OwnedSemaphorePermit {
+        Arc::new(Semaphore::new(1)).try_acquire_owned().unwrap()
+    }
+
+    // Lanes run while the test sleeps, not in between the pushes, so the queue count is exact.
+    #[tokio::test]
+    async fn test_same_chat_is_ordered_other_chats_are_not_blocked() {
+        let route = Arc::new(MockCallsRoute::new("bot"));
+        route.set_delay(10, Duration::from_millis(50));
+        let lanes = Lanes::new(UpdateKey::Chat, Arc::new(DeliveryStats::default()), Arc::new(InFlight::default()));
+
+        for (id, chat) in [(10, 1), (11, 1), (21, 2), (12, 1), (22, 2)] {
+            lanes.push(route.clone(), permit(), message(id, chat));
+        }
+        assert_eq!(lanes.len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let calls: Vec<i64> = route.get_calls().await.iter().map(|u| u["update_id"].as_i64().unwrap()).collect();
+
+        // chat 1 waits for its slow first update, chat 2 does not
+        let chat1: Vec<i64> = calls.iter().copied().filter(|id| *id < 20).collect();
+        assert_eq!(chat1, vec![10, 11, 12]);
+        assert_eq!(&calls[3..], &[11, 12]);
+        assert_eq!(lanes.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_updates_without_key_are_not_queued() {
+        let route = Arc::new(MockCallsRoute::new("bot"));
+        let lanes = Lanes::new(UpdateKey::Chat, Arc::new(DeliveryStats::default()), Arc::new(InFlight::default()));
+
+        lanes.push(route.clone(), permit(), json!({"update_id": 1, "poll": {"id": "x"}}));
+        assert_eq!(lanes.len(), 0);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(route.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_queued_updates_hold_their_delivery_slot() {
+        let route = Arc::new(MockCallsRoute::new("bot"));
+        route.set_delay(1, Duration::from_millis(50));
+        let lanes = Lanes::new(UpdateKey::Chat, Arc::new(DeliveryStats::default()), Arc::new(InFlight::default()));
+        let limit = Arc::new(Semaphore::new(3));
+
+        for id in 1..=3 {
+            lanes.push(route.clone(), limit.clone().try_acquire_owned().unwrap(), message(id, 1));
+        }
+        // two updates wait behind the slow first one, their slots stay taken
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limit.available_permits(), 0);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(route.count().await, 3);
+        assert_eq!(limit.available_permits(), 3);
+        assert_eq!(lanes.len(), 0);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+pub struct BreakerPolicy {
+    pub failure_threshold: u32,
+    pub error_rate: f64,
+    pub window: usize,
+    pub cooldown: Duration,
+    pub half_open_probes: u32,
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            error_rate: 0.5,
+            window: 20,
+            cooldown: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+}
+
+enum State {
+    Closed,
+    Open(Instant),
+    HalfOpen { probes: u32, succeeded: u32 },
+}
+
+struct Inner {
+    state: State,
+    consecutive_failures: u32,
+    outcomes: VecDeque<bool>,
+}
+
+/// Passive outlier detection for a single backend: opens after too many consecutive failures
+/// or a too high error rate over the last `window` deliveries, stays open for `cooldown`,
+/// then lets `half_open_probes` deliveries through and closes once all of them succeed.
+pub struct CircuitBreaker {
+    policy: BreakerPolicy,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: BreakerPolicy) -> Self {
+        Self {
+            policy,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                consecutive_failures: 0,
+                outcomes: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        let inner = self.inner.lock().expect("Circuit breaker lock poisoned");
+        match inner.state {
+            State::Closed => true,
+            State::Open(until) => Instant::now() >= until,
+            State::HalfOpen { probes, .. } => probes < self.policy.half_open_probes,
+        }
+    }
+
+    /// Asks for permission to send one delivery, turning an expired open circuit into half-open.
+    pub fn acquire(&self) -> bool {
+        let mut inner = self.inner.lock().expect("Circuit breaker lock poisoned");
+        match inner.state {
+            State::Closed => true,
+            State::Open(until) => {
+                if Instant::now() < until {
+                    return false;
+                }
+                inner.state = State::HalfOpen { probes: 1, succeeded: 0 };
+                true
+            }
+            State::HalfOpen { ref mut probes, .. } => {
+                if *probes >= self.policy.half_open_probes {
+                    return false;
+                }
+                *probes += 1;
+                true
+            }
+        }
+    }
+
+    /// Records the outcome of an acquired delivery. Returns `true` when this outcome opened the circuit.
+    pub fn record(&self, success: bool) -> bool {
+        let mut inner = self.inner.lock().expect("Circuit breaker lock poisoned");
+        match inner.state {
+            State::Closed => {
+                inner.outcomes.push_back(success);
+                if inner.outcomes.len() > self.policy.window {
+                    inner.outcomes.pop_front();
+                }
+                inner.consecutive_failures = if success { 0 } else { inner.consecutive_failures + 1 };
+
+                let failures = inner.outcomes.iter().filter(|ok| !**ok).count();
+                let rate_exceeded = inner.outcomes.len() >= self.policy.window
+                    && failures as f64 >= self.policy.error_rate * inner.outcomes.len() as f64;
+
+                if inner.consecutive_failures >= self.policy.failure_threshold || rate_exceeded {
+                    self.trip(&mut inner);
+                    return true;
+                }
+                false
+            }
+            State::HalfOpen { ref mut succeeded, .. } => {
+                if !success {
+                    self.trip(&mut inner);
+                    return true;
+                }
+                *succeeded += 1;
+                if *succeeded >= self.policy.half_open_probes {
+                    inner.state = State::Closed;
+                }
+                false
+            }
+            // Late result of a delivery that started before the circuit opened.
+            State::Open(_) => false,
+        }
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        let inner = self.inner.lock().expect("Circuit breaker lock poisoned");
+        match inner.state {
+            State::Closed => "closed",
+            State::Open(_) => "open",
+            State::HalfOpen { .. } => "half-open",
+        }
+    }
+
+    fn trip(&self, inner: &mut Inner) {
+        inner.state = State::Open(Instant::now() + self.policy.cooldown);
+        inner.consecutive_failures = 0;
+        inner.outcomes.clear();
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(BreakerPolicy {
+            failure_threshold: 3,
+            error_rate: 0.5,
+            window: 10,
+            cooldown,
+            half_open_probes: 2,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker(Duration::from_secs(60));
+
+        assert!(!breaker.record(false));
+        assert!(!breaker.record(false));
+        assert!(breaker.record(false));
+
+        assert_eq!(breaker.state_name(), "open");
+        assert!(!breaker.is_available());
+        assert!(!breaker.acquire());
+    }
+
+    #[test]
+    fn test_opens_on_error_rate() {
+        let breaker = breaker(Duration::from_secs(60));
+
+        // Never three failures in a row, but half of the window fails.
+        for i in 0..9 {
+            assert!(!breaker.record(i % 2 == 0));
+        }
+        assert!(breaker.record(false));
+        assert_eq!(breaker.state_name(), "open");
+    }
+
+    #[test]
+    fn test_half_open_closes_after_successful_probes() {
+        let breaker = breaker(Duration::from_millis(20));
+        for _ in 0..3 {
+            breaker.record(false);
+        }
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.is_available());
+
+        assert!(breaker.acquire());
+        assert!(breaker.acquire());
+        assert_eq!(breaker.state_name(), "half-open");
+        // Both probes are in flight, nothing else goes through.
+        assert!(!breaker.acquire());
+        assert!(!breaker.is_available());
+
+        breaker.record(true);
+        breaker.record(true);
+        assert_eq!(breaker.state_name(), "closed");
+        assert!(breaker.acquire());
+    }
+
+    #[test]
+    fn test_failed_probe_opens_again() {
+        let breaker = breaker(Duration::from_millis(20));
+        for _ in 0..3 {
+            breaker.record(false);
+        }
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.acquire());
+        assert!(breaker.record(false));
+
+        assert_eq!(breaker.state_name(), "open");
+        assert!(!breaker.is_available());
+    }
+}
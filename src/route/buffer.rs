@@ -0,0 +1,233 @@
+use crate::route::wal::{Persisted, Wal, WalPolicy, WalWriter};
+use crate::utils::update::update_id;
+
+use serde_json::Value;
+
+use std::collections::VecDeque;
+use std::io;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    Reject,
+    Block,
+}
+
+impl OverflowPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "drop-oldest",
+            OverflowPolicy::DropNewest => "drop-newest",
+            OverflowPolicy::Reject => "reject",
+            OverflowPolicy::Block => "block",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BufferLimits {
+    pub max_len: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        Self {
+            max_len: None,
+            max_bytes: None,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+pub fn size_of(update: &Value) -> usize {
+    update.to_string().len()
+}
+
+struct Buffered {
+    seq: u64,
+    update: Value,
+    size: usize,
+}
+
+/// Queue of updates waiting for a long-poll consumer that keeps track of its size in bytes
+/// and, when a write-ahead log is attached, queues every change for the log's writer thread.
+#[derive(Default)]
+pub struct UpdateBuffer {
+    items: VecDeque<Buffered>,
+    bytes: usize,
+    next_seq: u64,
+    wal: Option<WalWriter>,
+}
+
+impl UpdateBuffer {
+    /// Attaches a write-ahead log and loads the updates it still holds.
+    pub fn open_wal(&mut self, policy: WalPolicy) -> io::Result<()> {
+        let (wal, replay) = Wal::open(policy)?;
+
+        self.items.clear();
+        self.bytes = 0;
+        for (seq, update) in replay.updates {
+            let size = size_of(&update);
+            self.bytes += size;
+            self.items.push_back(Buffered { seq, update, size });
+        }
+        self.next_seq = replay.next_seq;
+        self.wal = Some(WalWriter::spawn(wal)?);
+        Ok(())
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.wal.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.items.iter().map(|item| &item.update)
+    }
+
+    /// Adds an update. With a write-ahead log attached the update is buffered right away and
+    /// the returned handle resolves once it is on disk; wait for it after releasing the lock.
+    pub fn push_back(&mut self, update: Value, size: usize) -> Option<Persisted> {
+        let seq = self.next_seq;
+        let persisted = self.wal.as_ref().map(|wal| wal.push(seq, update.clone()));
+
+        self.next_seq += 1;
+        self.bytes += size;
+        self.items.push_back(Buffered { seq, update, size });
+        persisted
+    }
+
+    /// Takes back an update that failed to reach the log. Returns false when a consumer
+    /// has already taken it.
+    pub fn withdraw(&mut self, seq: u64) -> bool {
+        let Some(index) = self.items.iter().position(|item| item.seq == seq) else {
+            return false;
+        };
+        self.remove(index);
+        true
+    }
+
+    pub fn pop_front(&mut self) -> Option<Value> {
+        let item = self.items.pop_front()?;
+        self.bytes -= item.size;
+        self.acknowledge(vec![item.seq]);
+        Some(item.update)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Value> {
+        let item = self.items.remove(index)?;
+        self.bytes -= item.size;
+        self.acknowledge(vec![item.seq]);
+        Some(item.update)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&Value) -> bool) {
+        let bytes = &mut self.bytes;
+        let mut removed = Vec::new();
+        self.items.retain(|item| {
+            let kept = keep(&item.update);
+            if !kept {
+                *bytes -= item.size;
+                removed.push(item.seq);
+            }
+            kept
+        });
+        self.acknowledge(removed);
+    }
+
+    fn acknowledge(&mut self, seqs: Vec<u64>) {
+        if let Some(wal) = &self.wal {
+            wal.ack(seqs);
+        }
+    }
+
+    /// Forgets confirmed updates the way Telegram does: a positive `offset` confirms every update
+    /// with a smaller `update_id`, a negative one keeps only the last `-offset` updates.
+    pub fn confirm(&mut self, offset: i64) {
+        if offset < 0 {
+            let keep = offset.unsigned_abs() as usize;
+            while self.len() > keep {
+                self.pop_front();
+            }
+        } else {
+            self.retain(|update| update_id(update).is_none_or(|id| id >= offset));
+        }
+    }
+
+    /// Whether an update of `size` bytes can be added. An empty buffer takes any update,
+    /// so a single oversized update cannot get stuck forever.
+    pub fn fits(&self, size: usize, limits: &BufferLimits) -> bool {
+        self.is_empty()
+            || (limits.max_len.is_none_or(|max| self.len() < max)
+                && limits.max_bytes.is_none_or(|max| self.bytes + size <= max))
+    }
+
+    /// Drops the oldest updates until one of `size` bytes fits. Returns how many were dropped.
+    pub fn make_room(&mut self, size: usize, limits: &BufferLimits) -> usize {
+        let mut dropped = 0;
+        while !self.fits(size, limits) {
+            self.pop_front();
+            dropped += 1;
+        }
+        dropped
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tracks_bytes() {
+        let mut buffer = UpdateBuffer::default();
+        let a = json!({"update_id": 1});
+        let b = json!({"update_id": 2, "message": {"text": "hi"}});
+
+        buffer.push_back(a.clone(), size_of(&a));
+        buffer.push_back(b.clone(), size_of(&b));
+        assert_eq!(buffer.bytes(), size_of(&a) + size_of(&b));
+
+        buffer.confirm(2);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.bytes(), size_of(&b));
+
+        buffer.pop_front();
+        assert_eq!(buffer.bytes(), 0);
+    }
+
+    #[test]
+    fn test_fits_and_make_room() {
+        let limits = BufferLimits { max_len: Some(3), max_bytes: Some(100), ..BufferLimits::default() };
+        let mut buffer = UpdateBuffer::default();
+
+        assert!(buffer.fits(500, &limits));
+        for i in 0..3 {
+            buffer.push_back(json!(i), 10);
+        }
+        assert!(!buffer.fits(10, &limits));
+
+        assert_eq!(buffer.make_room(10, &limits), 1);
+        assert_eq!(buffer.iter().next(), Some(&json!(1)));
+
+        assert_eq!(buffer.make_room(95, &limits), 2);
+        assert!(buffer.is_empty());
+    }
+}
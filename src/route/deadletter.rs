@@ -0,0 +1,80 @@
+use crate::base::{RouteableComponent, DeliveryResult};
+
+use serde_json::Value;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+const DEAD_LETTER_LIMIT: usize = 10000;
+
+tokio::task_local! {
+    static HELD: RefCell<Vec<DeadLetter>>;
+}
+
+/// Stores an update for a later redrive, dropping the oldest one when the stash is full.
+pub fn keep(stash: &Mutex<VecDeque<Value>>, update: Value) {
+    let mut stash = stash.lock().expect("Dead letters lock poisoned");
+    if stash.len() >= DEAD_LETTER_LIMIT {
+        stash.pop_front();
+    }
+    stash.push_back(update);
+}
+
+/// An update a route gave up on, on its way to the route's dead-letter queue.
+pub struct DeadLetter {
+    pub update: Value,
+    pub route: Arc<dyn RouteableComponent>,
+    pub stash: Arc<Mutex<VecDeque<Value>>>,
+    pub owner: String,
+}
+
+impl DeadLetter {
+    /// Keeps a copy for a later redrive and hands the update to the dead-letter route.
+    async fn bury(self) {
+        keep(&self.stash, self.update.clone());
+
+        let result = self.route.process(self.update).await;
+        if !result.is_delivered() {
+            eprintln!("Dead letter route for {} failed, {}", self.owner, result);
+        }
+    }
+
+    /// Buries the letter, unless a balancer above is still trying other routes with the
+    /// update. That balancer buries it once no route delivered the update.
+    pub async fn send(self) {
+        let mut letter = Some(self);
+        let _ = HELD.try_with(|held| held.borrow_mut().extend(letter.take()));
+        if let Some(letter) = letter {
+            letter.bury().await;
+        }
+    }
+}
+
+/// Dead letters of the children a balancer tried with one update.
+#[derive(Default)]
+pub struct HeldDeadLetters(Vec<DeadLetter>);
+
+impl HeldDeadLetters {
+    /// Runs one attempt of a child and holds back the dead letters it sends.
+    pub async fn attempt<F: Future<Output = DeliveryResult>>(&mut self, process: F) -> DeliveryResult {
+        let (result, letters) = HELD.scope(RefCell::new(Vec::new()), async {
+            let result = process.await;
+            (result, HELD.with(|held| held.take()))
+        }).await;
+        self.0.extend(letters);
+        result
+    }
+
+    /// Sends the held dead letters on when the update was not delivered in the end, and
+    /// drops them when another child delivered it.
+    pub async fn settle(self, result: &DeliveryResult) {
+        if result.is_delivered() {
+            return;
+        }
+        for letter in self.0 {
+            letter.send().await;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use reqwest::Client;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::interval;
+
+
+pub struct HealthPolicy {
+    pub url: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub healthy_threshold: u32,
+    pub unhealthy_threshold: u32,
+}
+
+/// Active health check of a backend: probes `url` every `interval` and flips the state after
+/// `unhealthy_threshold` failed or `healthy_threshold` successful probes in a row.
+pub struct HealthCheck {
+    policy: HealthPolicy,
+    healthy: AtomicBool,
+    started: AtomicBool,
+    streak: Mutex<(bool, u32)>,
+}
+
+impl HealthCheck {
+    pub fn new(policy: HealthPolicy) -> Self {
+        Self {
+            policy,
+            healthy: AtomicBool::new(true),
+            started: AtomicBool::new(false),
+            streak: Mutex::new((true, 0)),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Records one probe. Returns the new state when this probe changed it.
+    pub fn record(&self, ok: bool) -> Option<bool> {
+        let mut streak = self.streak.lock().expect("Health check lock poisoned");
+        if streak.0 == ok {
+            streak.1 += 1;
+        } else {
+            *streak = (ok, 1);
+        }
+
+        let threshold = if ok { self.policy.healthy_threshold } else { self.policy.unhealthy_threshold };
+        if streak.1 >= threshold && self.is_healthy() != ok {
+            self.healthy.store(ok, Ordering::Relaxed);
+            return Some(ok);
+        }
+        None
+    }
+
+    async fn probe(&self, client: &Client) -> bool {
+        match client.get(&self.policy.url).timeout(self.policy.timeout).send().await {
+            Ok(res) => res.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    /// Spawns the probing loop. Calling it again is a no-op.
+    pub fn start(self: &Arc<Self>, client: Client, name: String) {
+        if self.started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        // The loop ends once the route is gone, e.g. replaced by a reload.
+        let weak = Arc::downgrade(self);
+        let period = self.policy.interval;
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                let Some(this) = weak.upgrade() else {
+                    return;
+                };
+                let ok = this.probe(&client).await;
+                match this.record(ok) {
+                    Some(true) => println!("{} is healthy again", name),
+                    Some(false) => eprintln!("{} is unhealthy, health check {} failed", name, this.policy.url),
+                    None => {}
+                }
+            }
+        });
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{MockServer, Mock, ResponseTemplate};
+    use wiremock::matchers::{method, path};
+
+    fn policy(url: String) -> HealthPolicy {
+        HealthPolicy {
+            url,
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(200),
+            healthy_threshold: 2,
+            unhealthy_threshold: 3,
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let check = HealthCheck::new(policy("http://localhost/health".to_string()));
+
+        assert_eq!(check.record(false), None);
+        assert_eq!(check.record(false), None);
+        assert_eq!(check.record(true), None);
+        assert!(check.is_healthy());
+
+        assert_eq!(check.record(false), None);
+        assert_eq!(check.record(false), None);
+        assert_eq!(check.record(false), Some(false));
+        assert!(!check.is_healthy());
+
+        assert_eq!(check.record(true), None);
+        assert_eq!(check.record(true), Some(true));
+        assert!(check.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_background_probes_mark_backend_unhealthy() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let client = Client::builder().no_proxy().build().unwrap();
+        let check = Arc::new(HealthCheck::new(policy(format!("{}/health", mock_server.uri()))));
+        check.start(client.clone(), "backend".to_string());
+        check.start(client, "backend".to_string());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!check.is_healthy());
+    }
+}
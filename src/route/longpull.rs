@@ -1,13 +1,16 @@
-use crate::base::{Routeable, Serverable, Printable};
+use crate::base::{Routeable, Serverable, Printable, DeliveryResult};
+use crate::route::buffer::{UpdateBuffer, BufferLimits, OverflowPolicy, size_of};
+use crate::utils::update::update_id;
+use crate::route::wal::WalPolicy;
+use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
 use async_trait::async_trait;
 
-use std::collections::VecDeque;
-
 use axum::{extract::Form, routing::post, Json, Router}; 
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, Notify};
 use tokio::sync::mpsc::Sender;
 use tokio::time::timeout as tokio_timeout;
@@ -25,21 +28,88 @@ pub struct GetUpdatesParams {
 
 #[derive(Clone)] 
 pub struct LongPollRoute {
-    updates: Arc<Mutex<VecDeque<Value>>>,
+    updates: Arc<Mutex<UpdateBuffer>>,
     notify: Arc<Notify>,
+    space: Arc<Notify>,
+    limits: BufferLimits,
+    dropped: Arc<AtomicU64>,
     pub path: String,
+    max_poll_age: Option<Duration>,
+    last_poll: Arc<std::sync::Mutex<Instant>>,
+    active_polls: Arc<AtomicUsize>,
+    persistent: bool,
+}
+
+struct ActivePoll<'a> {
+    route: &'a LongPollRoute,
+}
+
+impl<'a> ActivePoll<'a> {
+    fn start(route: &'a LongPollRoute) -> Self {
+        route.active_polls.fetch_add(1, Ordering::Relaxed);
+        route.touch();
+        Self { route }
+    }
+}
+
+impl Drop for ActivePoll<'_> {
+    fn drop(&mut self) {
+        self.route.touch();
+        self.route.active_polls.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl LongPollRoute {
     pub fn new(path: String) -> Self {
         Self {
-            updates: Arc::new(Mutex::new(VecDeque::new())),
+            updates: Arc::new(Mutex::new(UpdateBuffer::default())),
             notify: Arc::new(Notify::new()),
+            space: Arc::new(Notify::new()),
+            limits: BufferLimits::default(),
+            dropped: Arc::new(AtomicU64::new(0)),
             path,
+            max_poll_age: None,
+            last_poll: Arc::new(std::sync::Mutex::new(Instant::now())),
+            active_polls: Arc::new(AtomicUsize::new(0)),
+            persistent: false,
         }
     }
 
+    pub fn set_max_poll_age(&mut self, max_poll_age: Option<Duration>) {
+        self.max_poll_age = max_poll_age;
+    }
+
+    pub fn set_limits(&mut self, limits: BufferLimits) {
+        self.limits = limits;
+    }
+
+    /// Keeps the buffer in a write-ahead log and loads whatever a previous run left there.
+    pub fn set_wal(&mut self, policy: WalPolicy) -> std::io::Result<()> {
+        let mut buffer = self.updates.try_lock().expect("Long-poll buffer is in use during setup");
+        buffer.open_wal(policy)?;
+        self.persistent = true;
+        Ok(())
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    fn touch(&self) {
+        *self.last_poll.lock().expect("Last poll lock poisoned") = Instant::now();
+    }
+
+    /// A consumer is considered alive while it is polling or has polled within `max_poll_age`.
+    fn is_healthy(&self) -> bool {
+        let Some(max_poll_age) = self.max_poll_age else {
+            return true;
+        };
+        self.active_polls.load(Ordering::Relaxed) > 0
+            || self.last_poll.lock().expect("Last poll lock poisoned").elapsed() <= max_poll_age
+    }
+
     pub async fn handle_request(&self, params: GetUpdatesParams) -> Json<Value>{
+        let _active = ActivePoll::start(self);
 
         let updates = self.updates.clone();
         let notify = self.notify.clone();
@@ -48,23 +118,29 @@ impl LongPollRoute {
         let start_time = tokio::time::Instant::now();
         let duration = Duration::from_secs(timeout_sec);
 
+        if let Some(offset) = params.offset {
+            updates.lock().await.confirm(offset);
+            self.space.notify_waiters();
+        }
+
         loop {
             {
                 let mut lock = updates.lock().await;
 
                 if !lock.is_empty() {
 
-                    let mut batch = Vec::new();
-
                     let limit = params.limit.unwrap_or(1000) as usize;
 
-                    while batch.len() < limit {
-                        if let Some(upd) = lock.pop_front() {
-                            batch.push(upd);
-                        } else {
-                            break;
+                    let batch: Vec<Value> = lock.iter().take(limit).cloned().collect();
+
+                    // Updates stay buffered until a later poll confirms them with its offset.
+                    // Ones without an `update_id` can never be confirmed, so they are handed out once.
+                    for i in (0..batch.len()).rev() {
+                        if update_id(&batch[i]).is_none() {
+                            lock.remove(i);
                         }
                     }
+                    self.space.notify_waiters();
 
                     return Json(json!({
                         "ok": true,
@@ -90,10 +166,77 @@ impl LongPollRoute {
 
 #[async_trait]
 impl Routeable for LongPollRoute {
-    async fn process(&self, update: Value) {
-        let mut lock = self.updates.lock().await;
-        lock.push_back(update);
-        self.notify.notify_waiters();
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let size = size_of(&update);
+
+        loop {
+            let mut lock = self.updates.lock().await;
+
+            if !lock.fits(size, &self.limits) {
+                match self.limits.overflow {
+                    OverflowPolicy::DropOldest => {
+                        let dropped = lock.make_room(size, &self.limits);
+                        self.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+                        eprintln!("Buffer of {} is full, dropped {} oldest updates", self.path, dropped);
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return DeliveryResult::BackendError(format!("buffer of {} is full, update dropped", self.path));
+                    }
+                    OverflowPolicy::Reject => {
+                        return DeliveryResult::Rejected(format!("buffer of {} is full", self.path));
+                    }
+                    OverflowPolicy::Block => {
+                        // Register for the wakeup before releasing the lock, so a consumer
+                        // freeing space in between is not missed.
+                        let space = self.space.notified();
+                        tokio::pin!(space);
+                        space.as_mut().enable();
+                        drop(lock);
+                        space.await;
+                        continue;
+                    }
+                }
+            }
+
+            let persisted = lock.push_back(update, size);
+            drop(lock);
+            self.notify.notify_waiters();
+
+            // The log is written by its own thread, the buffer is not held while waiting for it.
+            if let Some(persisted) = persisted {
+                let seq = persisted.seq();
+                if let Err(err) = persisted.wait().await {
+                    if self.updates.lock().await.withdraw(seq) {
+                        self.space.notify_waiters();
+                        return DeliveryResult::BackendError(format!("failed to persist update for {}: {}", self.path, err));
+                    }
+                    eprintln!("Failed to persist update for {}, it was already handed out: {}", self.path, err);
+                }
+            }
+            return DeliveryResult::Delivered;
+        }
+    }
+
+    async fn pending(&self) -> usize {
+        self.updates.lock().await.len()
+    }
+
+    async fn is_available(&self) -> bool {
+        self.is_healthy()
+    }
+
+    fn poll_path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn register(&self) {
+        let mut registry = LONGPOLL_REGISTRY.write().expect("Registry lock poisoned");
+        registry.insert(self.path.clone(), Arc::new(self.clone()));
     }
 }
 
@@ -125,11 +268,25 @@ impl Printable for LongPollRoute {
     }
 
     async fn json_struct(&self) -> Value {
+        let (buffered, buffered_bytes, persistent) = {
+            let buffer = self.updates.lock().await;
+            (buffer.len(), buffer.bytes(), buffer.is_persistent())
+        };
+
         json!({
             "type": "longpoll",
             "options": {
-                "path": self.path
-            }
+                "path": self.path,
+                "max_poll_age": self.max_poll_age.map(|age| age.as_secs()),
+                "max_len": self.limits.max_len,
+                "max_bytes": self.limits.max_bytes,
+                "overflow": self.limits.overflow.name(),
+                "persistent": persistent
+            },
+            "healthy": self.is_healthy(),
+            "buffered": buffered,
+            "buffered_bytes": buffered_bytes,
+            "dropped": self.dropped.load(Ordering::Relaxed)
         })
     }
 }
@@ -158,8 +315,8 @@ mod tests {
     async fn test_basic_process_and_retrieve() {
         let route = LongPollRoute::new("/bot/updates".to_string());
 
-        route.process(json!({"update_id": 1})).await;
-        route.process(json!({"update_id": 2})).await;
+        let _ = route.process(json!({"update_id": 1})).await;
+        let _ = route.process(json!({"update_id": 2})).await;
 
         let response = route.handle_request(default_params()).await;
         
@@ -171,12 +328,168 @@ mod tests {
         assert_eq!(results[1]["update_id"], 2);
     }
 
+    #[tokio::test]
+    async fn test_consumer_must_poll_within_max_age() {
+        let mut route = LongPollRoute::new("/bot/updates".to_string());
+        route.set_max_poll_age(Some(Duration::from_millis(50)));
+        assert!(route.is_available().await);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(!route.is_available().await);
+        assert_eq!(route.json_struct().await["healthy"], false);
+
+        let _ = route.handle_request(default_params()).await;
+        assert!(route.is_available().await);
+
+        // a consumer waiting in a long poll counts as alive
+        let waiting = route.clone();
+        let handle = tokio::spawn(async move {
+            waiting.handle_request(GetUpdatesParams { timeout: Some(1), ..default_params() }).await
+        });
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(route.is_available().await);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_updates_are_redelivered() {
+        let route = LongPollRoute::new("/bot/updates".to_string());
+
+        for i in 1..=3 {
+            let _ = route.process(json!({"update_id": i})).await;
+        }
+
+        let first = route.handle_request(default_params()).await.0;
+        // the consumer crashed before confirming, so the next poll gets the same batch
+        let second = route.handle_request(default_params()).await.0;
+        assert_eq!(first["result"], second["result"]);
+        assert_eq!(second["result"].as_array().unwrap().len(), 3);
+
+        let params = GetUpdatesParams { offset: Some(3), ..default_params() };
+        let rest = route.handle_request(params).await.0;
+        assert_eq!(rest["result"], json!([{"update_id": 3}]));
+
+        let params = GetUpdatesParams { offset: Some(4), ..default_params() };
+        let empty = route.handle_request(params).await.0;
+        assert_eq!(empty["result"], json!([]));
+        assert_eq!(route.pending().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_negative_offset_keeps_last_updates() {
+        let route = LongPollRoute::new("/bot/updates".to_string());
+
+        for i in 1..=5 {
+            let _ = route.process(json!({"update_id": i})).await;
+        }
+
+        let params = GetUpdatesParams { offset: Some(-2), ..default_params() };
+        let body = route.handle_request(params).await.0;
+        assert_eq!(body["result"], json!([{"update_id": 4}, {"update_id": 5}]));
+    }
+
+    fn bounded(max_len: usize, overflow: OverflowPolicy) -> LongPollRoute {
+        let mut route = LongPollRoute::new("/bot/updates".to_string());
+        route.set_limits(BufferLimits { max_len: Some(max_len), max_bytes: None, overflow });
+        route
+    }
+
+    #[tokio::test]
+    async fn test_overflow_drop_oldest() {
+        let route = bounded(2, OverflowPolicy::DropOldest);
+
+        for i in 1..=3 {
+            assert!(route.process(json!({"update_id": i})).await.is_delivered());
+        }
+
+        let body = route.handle_request(default_params()).await.0;
+        assert_eq!(body["result"], json!([{"update_id": 2}, {"update_id": 3}]));
+        assert_eq!(route.json_struct().await["dropped"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_overflow_drop_newest_and_reject() {
+        let newest = bounded(1, OverflowPolicy::DropNewest);
+        let _ = newest.process(json!({"update_id": 1})).await;
+        assert!(matches!(newest.process(json!({"update_id": 2})).await, DeliveryResult::BackendError(_)));
+        let body = newest.handle_request(default_params()).await.0;
+        assert_eq!(body["result"], json!([{"update_id": 1}]));
+
+        let reject = bounded(1, OverflowPolicy::Reject);
+        let _ = reject.process(json!({"update_id": 1})).await;
+        assert!(reject.process(json!({"update_id": 2})).await.is_rejected());
+        assert_eq!(reject.pending().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes_limit() {
+        let mut route = LongPollRoute::new("/bot/updates".to_string());
+        let update = json!({"update_id": 1});
+        route.set_limits(BufferLimits {
+            max_len: None,
+            max_bytes: Some(size_of(&update) * 2),
+            overflow: OverflowPolicy::Reject,
+        });
+
+        assert!(route.process(json!({"update_id": 1})).await.is_delivered());
+        assert!(route.process(json!({"update_id": 2})).await.is_delivered());
+        assert!(route.process(json!({"update_id": 3})).await.is_rejected());
+    }
+
+    #[tokio::test]
+    async fn test_overflow_block_waits_for_confirmation() {
+        let route = bounded(1, OverflowPolicy::Block);
+        let _ = route.process(json!({"update_id": 1})).await;
+
+        let blocked = route.clone();
+        let handle = tokio::spawn(async move {
+            blocked.process(json!({"update_id": 2})).await
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handle.is_finished());
+
+        let _ = route.handle_request(GetUpdatesParams { offset: Some(2), ..default_params() }).await;
+        assert!(handle.await.unwrap().is_delivered());
+
+        let body = route.handle_request(default_params()).await.0;
+        assert_eq!(body["result"], json!([{"update_id": 2}]));
+    }
+
+    #[tokio::test]
+    async fn test_wal_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("tgin-longpoll-wal-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = || WalPolicy { dir: dir.clone(), segment_size: 1 << 20, fsync: false };
+
+        {
+            let mut route = LongPollRoute::new("/bot/updates".to_string());
+            route.set_wal(policy()).unwrap();
+            for i in 1..=3 {
+                let _ = route.process(json!({"update_id": i})).await;
+            }
+            // the consumer confirms the first update, then tgin goes down
+            let _ = route.handle_request(GetUpdatesParams { offset: Some(2), ..default_params() }).await;
+        }
+
+        let mut route = LongPollRoute::new("/bot/updates".to_string());
+        route.set_wal(policy()).unwrap();
+        assert_eq!(route.pending().await, 2);
+
+        let _ = route.process(json!({"update_id": 4})).await;
+        let body = route.handle_request(default_params()).await.0;
+        assert_eq!(body["result"], json!([{"update_id": 2}, {"update_id": 3}, {"update_id": 4}]));
+        assert_eq!(route.json_struct().await["options"]["persistent"], true);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_limit_batching() {
         let route = LongPollRoute::new("/test".to_string());
 
         for i in 0..10 {
-            route.process(json!({"id": i})).await;
+            let _ = route.process(json!({"id": i})).await;
         }
         // Запрашиваем только 4
         let params = GetUpdatesParams {
@@ -233,7 +546,7 @@ mod tests {
 
         tokio::time::sleep(Duration::from_millis(100)).await;
 
-        route.process(json!({"msg": "hello"})).await;
+        let _ = route.process(json!({"msg": "hello"})).await;
         let response = handle.await.unwrap();
         let body: Value = serde_json::to_value(response.0).unwrap();
         let results = body.get("result").unwrap().as_array().unwrap();
@@ -247,7 +560,7 @@ mod tests {
         let path = "/bot123/getUpdates";
         let route = LongPollRoute::new(path.to_string());
         
-        route.process(json!({"ok": true})).await;
+        let _ = route.process(json!({"ok": true})).await;
 
         let app = Router::new();
         let app = route.set_server(app).await;
@@ -273,6 +586,22 @@ mod tests {
         assert_eq!(body_json["result"].as_array().unwrap().len(), 1);
     }
     
+    #[tokio::test]
+    async fn test_pending_is_queue_depth() {
+        let route = LongPollRoute::new("/test".to_string());
+
+        let _ = route.process(json!({"update_id": 1})).await;
+        let _ = route.process(json!({"update_id": 2})).await;
+        assert_eq!(route.pending().await, 2);
+
+        // handed out but not confirmed yet
+        let _ = route.handle_request(default_params()).await;
+        assert_eq!(route.pending().await, 2);
+
+        let _ = route.handle_request(GetUpdatesParams { offset: Some(3), ..default_params() }).await;
+        assert_eq!(route.pending().await, 0);
+    }
+
     #[tokio::test]
     async fn test_printable_json_struct() {
         let route = LongPollRoute::new("/my/path".to_string());
@@ -1,3 +1,9 @@
 
 pub mod webhook;
 pub mod longpull;
+pub mod retry;
+pub mod breaker;
+pub mod health;
+pub mod buffer;
+pub mod wal;
+pub mod deadletter;
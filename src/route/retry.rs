@@ -0,0 +1,105 @@
+use rand::Rng;
+
+use std::time::Duration;
+
+
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    pub jitter: f64,
+    pub retry_statuses: Vec<u16>,
+    pub honor_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+            retry_statuses: vec![429, 500, 502, 503, 504],
+            honor_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn is_retryable_status(&self, status: u16) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// Delay before the attempt that follows `attempt` (counting from 1): exponential
+    /// backoff with jitter, never shorter than an honored `Retry-After`, capped by `max_backoff`.
+    pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let mut delay = self.base_backoff.saturating_mul(exp).min(self.max_backoff);
+
+        if self.jitter > 0.0 {
+            let jitter = self.jitter.min(1.0) * rand::rng().random::<f64>();
+            delay = delay.mul_f64(1.0 - jitter);
+        }
+
+        if let (true, Some(retry_after)) = (self.honor_retry_after, retry_after) {
+            delay = delay.max(retry_after);
+        }
+
+        delay.min(self.max_backoff)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(1, None), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2, None), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4, None), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5, None), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(40, None), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_jitter_only_shortens_delay() {
+        let policy = RetryPolicy { jitter: 0.5, ..policy() };
+
+        for _ in 0..100 {
+            let delay = policy.backoff(3, None);
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = policy();
+        assert_eq!(policy.backoff(1, Some(Duration::from_millis(700))), Duration::from_millis(700));
+        assert_eq!(policy.backoff(1, Some(Duration::from_secs(60))), Duration::from_millis(1000));
+
+        let policy = RetryPolicy { honor_retry_after: false, ..policy };
+        assert_eq!(policy.backoff(1, Some(Duration::from_millis(700))), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        let policy = policy();
+        assert!(policy.is_retryable_status(503));
+        assert!(policy.is_retryable_status(429));
+        assert!(!policy.is_retryable_status(400));
+    }
+}
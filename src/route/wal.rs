@@ -0,0 +1,458 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use tokio::sync::oneshot;
+
+
+pub struct WalPolicy {
+    pub dir: PathBuf,
+    pub segment_size: u64,
+    pub fsync: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Record {
+    Push { seq: u64, update: Value },
+    Ack { seqs: Vec<u64> },
+}
+
+/// What a previous run left in the log: buffered updates in their original order with their
+/// sequence numbers, and the first sequence number not used by any record.
+pub struct Replay {
+    pub updates: Vec<(u64, Value)>,
+    pub next_seq: u64,
+}
+
+#[derive(Default)]
+struct Segment {
+    pushes: usize,
+    live: usize,
+}
+
+/// Write-ahead log of a long-poll buffer: every buffered update and every removal is appended as
+/// a JSON line to the current segment file, so the buffer can be rebuilt after a restart.
+///
+/// Segments are rotated once they grow past `segment_size`. A segment is deleted as soon as it and
+/// every older segment hold no live update; on rotation the live updates of a mostly acknowledged
+/// oldest segment are read back and copied forward so that one stuck update does not pin the whole log.
+///
+/// All calls block on the file system, so outside of setup the log is driven by a [`WalWriter`].
+pub struct Wal {
+    policy: WalPolicy,
+    file: File,
+    current: u64,
+    current_bytes: u64,
+    segments: BTreeMap<u64, Segment>,
+    location: HashMap<u64, u64>,
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.wal", id))
+}
+
+fn open_segment(dir: &Path, id: u64) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(segment_path(dir, id))
+}
+
+impl Wal {
+    /// Opens the log in `policy.dir`, replaying existing segments.
+    pub fn open(policy: WalPolicy) -> io::Result<(Self, Replay)> {
+        fs::create_dir_all(&policy.dir)?;
+
+        let mut ids: Vec<u64> = fs::read_dir(&policy.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_suffix(".wal")?.parse().ok()
+            })
+            .collect();
+        ids.sort_unstable();
+
+        let mut updates: BTreeMap<u64, Value> = BTreeMap::new();
+        let mut location = HashMap::new();
+        let mut segments: BTreeMap<u64, Segment> = BTreeMap::new();
+        let mut next_seq = 0;
+
+        for id in &ids {
+            segments.insert(*id, Segment::default());
+            let reader = BufReader::new(File::open(segment_path(&policy.dir, *id))?);
+            for line in reader.lines() {
+                let line = line?;
+                // A crash in the middle of a write leaves a torn last line, it is skipped.
+                let Ok(record) = serde_json::from_str::<Record>(&line) else {
+                    eprintln!("Skipping a damaged record in segment {} of {}", id, policy.dir.display());
+                    continue;
+                };
+                match record {
+                    Record::Push { seq, update } => {
+                        if let Some(segment) = segments.get_mut(id) {
+                            segment.pushes += 1;
+                        }
+                        next_seq = next_seq.max(seq + 1);
+                        updates.insert(seq, update);
+                        location.insert(seq, *id);
+                    }
+                    Record::Ack { seqs } => {
+                        for seq in seqs {
+                            next_seq = next_seq.max(seq + 1);
+                            updates.remove(&seq);
+                            location.remove(&seq);
+                        }
+                    }
+                }
+            }
+        }
+
+        for id in location.values() {
+            if let Some(segment) = segments.get_mut(id) {
+                segment.live += 1;
+            }
+        }
+
+        // Writes always go to a fresh segment, never after a possibly torn line.
+        let current = ids.last().map_or(0, |id| id + 1);
+        segments.insert(current, Segment::default());
+
+        let mut wal = Self {
+            file: open_segment(&policy.dir, current)?,
+            policy,
+            current,
+            current_bytes: 0,
+            segments,
+            location,
+        };
+        wal.collect_garbage()?;
+
+        Ok((wal, Replay { updates: updates.into_iter().collect(), next_seq }))
+    }
+
+    pub fn push(&mut self, seq: u64, update: &Value) -> io::Result<()> {
+        self.append(&Record::Push { seq, update: update.clone() })?;
+        self.track(seq);
+        Ok(())
+    }
+
+    fn track(&mut self, seq: u64) {
+
+        if let Some(old) = self.location.insert(seq, self.current) {
+            self.forget(old);
+        }
+        let segment = self.segments.entry(self.current).or_default();
+        segment.pushes += 1;
+        segment.live += 1;
+    }
+
+    pub fn ack(&mut self, seqs: Vec<u64>) -> io::Result<()> {
+        if seqs.is_empty() {
+            return Ok(());
+        }
+        for seq in &seqs {
+            if let Some(segment) = self.location.remove(seq) {
+                self.forget(segment);
+            }
+        }
+        self.append(&Record::Ack { seqs })?;
+        self.collect_garbage()
+    }
+
+    pub fn needs_rotation(&self) -> bool {
+        self.current_bytes >= self.policy.segment_size
+    }
+
+    /// Starts a new segment.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.current += 1;
+        self.current_bytes = 0;
+        self.file = open_segment(&self.policy.dir, self.current)?;
+        self.segments.insert(self.current, Segment::default());
+
+        let oldest = self.segments.iter().next().map(|(id, segment)| (*id, segment.live * 2 <= segment.pushes));
+        if let Some((oldest, true)) = oldest {
+            if oldest != self.current {
+                for (seq, update) in self.live_updates(oldest)? {
+                    self.push(seq, &update)?;
+                }
+                // The copies must be on disk before the segment holding the originals goes away.
+                self.sync()?;
+            }
+        }
+
+        self.collect_garbage()
+    }
+
+    /// Reads back the updates of `segment` that have not been acknowledged or moved since.
+    fn live_updates(&self, segment: u64) -> io::Result<Vec<(u64, Value)>> {
+        let reader = BufReader::new(File::open(segment_path(&self.policy.dir, segment))?);
+        let mut updates = Vec::new();
+        for line in reader.lines() {
+            if let Ok(Record::Push { seq, update }) = serde_json::from_str::<Record>(&line?) {
+                if self.location.get(&seq) == Some(&segment) {
+                    updates.push((seq, update));
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Flushes appended records to disk when the policy asks for it.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.policy.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn forget(&mut self, segment: u64) {
+        if let Some(segment) = self.segments.get_mut(&segment) {
+            segment.live = segment.live.saturating_sub(1);
+        }
+    }
+
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.current_bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn collect_garbage(&mut self) -> io::Result<()> {
+        while let Some((&id, segment)) = self.segments.iter().next() {
+            if id == self.current || segment.live > 0 {
+                break;
+            }
+            self.segments.remove(&id);
+            match fs::remove_file(segment_path(&self.policy.dir, id)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+}
+
+
+enum Command {
+    Push { seq: u64, update: Value, done: oneshot::Sender<io::Result<()>> },
+    Ack { seqs: Vec<u64> },
+}
+
+/// Resolves once a pushed update has reached the log.
+pub struct Persisted {
+    seq: u64,
+    done: oneshot::Receiver<io::Result<()>>,
+}
+
+impl Persisted {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub async fn wait(self) -> io::Result<()> {
+        self.done.await.unwrap_or_else(|_| Err(io::Error::other("write-ahead log writer stopped")))
+    }
+}
+
+/// Runs a [`Wal`] on a dedicated thread, so the buffer only queues records and never waits
+/// for the disk while it is locked.
+///
+/// Records are written in the order they are queued. Everything queued while the thread was
+/// busy is written as one batch with a single fsync.
+pub struct WalWriter {
+    commands: Option<mpsc::Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WalWriter {
+    pub fn spawn(wal: Wal) -> io::Result<Self> {
+        let (commands, rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("tgin-wal".to_string())
+            .spawn(move || run(wal, rx))?;
+        Ok(Self { commands: Some(commands), thread: Some(thread) })
+    }
+
+    pub fn push(&self, seq: u64, update: Value) -> Persisted {
+        let (done, rx) = oneshot::channel();
+        self.send(Command::Push { seq, update, done });
+        Persisted { seq, done: rx }
+    }
+
+    pub fn ack(&self, seqs: Vec<u64>) {
+        if !seqs.is_empty() {
+            self.send(Command::Ack { seqs });
+        }
+    }
+
+    fn send(&self, command: Command) {
+        // The thread only stops once this handle is dropped, a dropped `done` reports it anyway.
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+}
+
+impl Drop for WalWriter {
+    // Waits for the queued records, so the log is complete once the buffer is gone.
+    fn drop(&mut self) {
+        self.commands.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(mut wal: Wal, commands: mpsc::Receiver<Command>) {
+    while let Ok(first) = commands.recv() {
+        let mut waiting = Vec::new();
+        for command in std::iter::once(first).chain(commands.try_iter()) {
+            match command {
+                Command::Push { seq, update, done } => {
+                    let result = wal.push(seq, &update);
+                    waiting.push((done, result));
+                }
+                Command::Ack { seqs } => {
+                    // A lost acknowledgement only means the update may be delivered again
+                    // after a restart, so it is logged rather than undone.
+                    if let Err(err) = wal.ack(seqs) {
+                        eprintln!("Failed to write acknowledgement to the write-ahead log: {}", err);
+                    }
+                }
+            }
+        }
+
+        let synced = wal.sync();
+        for (done, result) in waiting {
+            let result = result.and_then(|()| match &synced {
+                Ok(()) => Ok(()),
+                Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+            });
+            let _ = done.send(result);
+        }
+
+        if wal.needs_rotation() {
+            if let Err(err) = wal.rotate() {
+                eprintln!("Failed to rotate the write-ahead log: {}", err);
+            }
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tgin-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn policy(dir: &Path, segment_size: u64) -> WalPolicy {
+        WalPolicy { dir: dir.to_path_buf(), segment_size, fsync: false }
+    }
+
+    #[test]
+    fn test_replays_unacknowledged_updates() {
+        let dir = temp_dir("replay");
+        {
+            let (mut wal, replay) = Wal::open(policy(&dir, 1 << 20)).unwrap();
+            assert!(replay.updates.is_empty());
+            assert_eq!(replay.next_seq, 0);
+
+            for seq in 0..5 {
+                wal.push(seq, &json!({"update_id": seq})).unwrap();
+            }
+            wal.ack(vec![0, 1, 3]).unwrap();
+        }
+
+        let (_, replay) = Wal::open(policy(&dir, 1 << 20)).unwrap();
+        assert_eq!(replay.updates, vec![(2, json!({"update_id": 2})), (4, json!({"update_id": 4}))]);
+        assert_eq!(replay.next_seq, 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_last_line_is_skipped() {
+        let dir = temp_dir("torn");
+        {
+            let (mut wal, _) = Wal::open(policy(&dir, 1 << 20)).unwrap();
+            wal.push(0, &json!({"update_id": 1})).unwrap();
+            wal.file.write_all(b"{\"Push\":{\"seq\":1,\"upd").unwrap();
+        }
+
+        let (_, replay) = Wal::open(policy(&dir, 1 << 20)).unwrap();
+        assert_eq!(replay.updates, vec![(0, json!({"update_id": 1}))]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_and_compaction() {
+        let dir = temp_dir("rotation");
+        let (mut wal, _) = Wal::open(policy(&dir, 64)).unwrap();
+
+        for seq in 0..20 {
+            wal.push(seq, &json!({"update_id": seq})).unwrap();
+            if wal.needs_rotation() {
+                wal.rotate().unwrap();
+            }
+        }
+        assert!(wal.segment_count() > 3);
+
+        // everything but the very first update is acknowledged
+        wal.ack((1..20).collect()).unwrap();
+
+        // the first segment is copied forward on rotation and the old ones go away
+        wal.rotate().unwrap();
+        assert_eq!(wal.segment_count(), 1);
+        drop(wal);
+
+        let files = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, 1);
+        let (_, replay) = Wal::open(policy(&dir, 64)).unwrap();
+        assert_eq!(replay.updates, vec![(0, json!({"update_id": 0}))]);
+        assert_eq!(replay.next_seq, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_writer_thread_flushes_before_drop() {
+        let dir = temp_dir("writer");
+        {
+            let (wal, _) = Wal::open(policy(&dir, 64)).unwrap();
+            let writer = WalWriter::spawn(wal).unwrap();
+
+            let pushed: Vec<Persisted> = (0..10).map(|seq| writer.push(seq, json!({"update_id": seq}))).collect();
+            for persisted in pushed {
+                persisted.wait().await.unwrap();
+            }
+            // acknowledgements are not awaited, dropping the writer still writes them
+            writer.ack((0..9).collect());
+        }
+
+        let (_, replay) = Wal::open(policy(&dir, 64)).unwrap();
+        assert_eq!(replay.updates, vec![(9, json!({"update_id": 9}))]);
+        assert_eq!(replay.next_seq, 10);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,55 @@
-use crate::base::{Routeable, Serverable, Printable};
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult};
+use crate::route::retry::RetryPolicy;
+use crate::route::breaker::CircuitBreaker;
+use crate::route::health::HealthCheck;
+use crate::route::deadletter::{keep, DeadLetter};
 use async_trait::async_trait;
+use axum::Router;
 use reqwest::Client;
+use reqwest::header::RETRY_AFTER;
 use serde_json::{Value, json};
+use tokio::sync::mpsc::Sender;
+use tokio::time::sleep;
 
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn start(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Attempt {
+    result: DeliveryResult,
+    retryable: bool,
+    retry_after: Option<Duration>,
+}
 
 pub struct WebhookRoute {
     client: Client,
     url: String,
+    timeout: Duration,
+    in_flight: AtomicUsize,
+    retry: RetryPolicy,
+    dead_letter: Option<Arc<dyn RouteableComponent>>,
+    dead_letters: Arc<Mutex<VecDeque<Value>>>,
+    breaker: Option<CircuitBreaker>,
+    health: Option<Arc<HealthCheck>>,
 }
 
 impl WebhookRoute {
@@ -15,35 +57,212 @@ impl WebhookRoute {
         Self {
             client: Client::new(),
             url,
+            timeout: DEFAULT_TIMEOUT,
+            in_flight: AtomicUsize::new(0),
+            retry: RetryPolicy::default(),
+            dead_letter: None,
+            dead_letters: Arc::new(Mutex::new(VecDeque::new())),
+            breaker: None,
+            health: None,
         }
     }
 
-    pub fn set_client(&mut self, client: Client) {
-        self.client = client;
+    /// How long one attempt may take, a backend that hangs would hold the update forever otherwise.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_retry(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub fn set_dead_letter(&mut self, route: Arc<dyn RouteableComponent>) {
+        self.dead_letter = Some(route);
+    }
+
+    /// Shares the dead letters of a route this one replaces, including whatever
+    /// its deliveries still in flight bury there.
+    pub fn keep_dead_letters(&mut self, previous: &WebhookRoute) {
+        self.dead_letters = previous.dead_letters.clone();
+    }
+
+    pub fn set_breaker(&mut self, breaker: CircuitBreaker) {
+        self.breaker = Some(breaker);
+    }
+
+    pub fn set_health_check(&mut self, health: HealthCheck) {
+        self.health = Some(Arc::new(health));
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.health.as_ref().is_none_or(|health| health.is_healthy())
+    }
+
+    async fn send(&self, update: &Value) -> Attempt {
+        match self.client.post(&self.url).timeout(self.timeout).json(update).send().await {
+            Ok(res) if res.status().is_success() => Attempt {
+                result: DeliveryResult::Delivered,
+                retryable: false,
+                retry_after: None,
+            },
+            Ok(res) => Attempt {
+                result: DeliveryResult::BackendError(format!("{} responded with {}", self.url, res.status())),
+                retryable: self.retry.is_retryable_status(res.status().as_u16()),
+                retry_after: res.headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs),
+            },
+            Err(err) if err.is_timeout() => Attempt {
+                result: DeliveryResult::Timeout(err.to_string()),
+                retryable: true,
+                retry_after: None,
+            },
+            Err(err) => Attempt {
+                result: DeliveryResult::BackendError(err.to_string()),
+                retryable: true,
+                retry_after: None,
+            },
+        }
+    }
+
+    /// Sends the update with retries, without dead-lettering it on failure.
+    async fn deliver(&self, update: &Value) -> DeliveryResult {
+        if let Some(breaker) = &self.breaker {
+            if !breaker.acquire() {
+                return DeliveryResult::Rejected(format!("circuit for {} is open", self.url));
+            }
+        }
+
+        let _in_flight = InFlight::start(&self.in_flight);
+
+        let mut attempt = 1;
+        let result = loop {
+            let outcome = self.send(update).await;
+
+            if outcome.result.is_delivered() || !outcome.retryable || attempt >= self.retry.max_attempts {
+                break outcome.result;
+            }
+
+            sleep(self.retry.backoff(attempt, outcome.retry_after)).await;
+            attempt += 1;
+        };
+
+        if let Some(breaker) = &self.breaker {
+            if breaker.record(result.is_delivered()) {
+                eprintln!("Circuit for {} opened after {}", self.url, result);
+            }
+        }
+        result
+    }
+
+    async fn bury(&self, update: Value) {
+        let Some(dead_letter) = &self.dead_letter else {
+            return;
+        };
+
+        DeadLetter {
+            update,
+            route: dead_letter.clone(),
+            stash: self.dead_letters.clone(),
+            owner: self.url.clone(),
+        }.send().await;
+    }
+
+    fn dead_letters_count(&self) -> usize {
+        self.dead_letters.lock().expect("Dead letters lock poisoned").len()
     }
 }
 
 #[async_trait]
 impl Routeable for WebhookRoute {
-    async fn process(&self, update: Value) {
-        let _ = self.client.post(&self.url).json(&update).send().await;
+    async fn process(&self, update: Value) -> DeliveryResult {
+        let result = self.deliver(&update).await;
+        if !result.is_delivered() {
+            // Dead-lettered unless a balancer above offers it to another route.
+            self.bury(update).await;
+        }
+        result
+    }
+
+    async fn pending(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    async fn is_available(&self) -> bool {
+        self.is_healthy() && self.breaker.as_ref().is_none_or(|breaker| breaker.is_available())
+    }
+
+    async fn start_health_checks(&self) {
+        if let Some(health) = &self.health {
+            health.start(self.client.clone(), format!("webhook {}", self.url));
+        }
+        if let Some(dead_letter) = &self.dead_letter {
+            dead_letter.start_health_checks().await;
+        }
+    }
+
+    async fn redrive(&self) -> Vec<DeliveryResult> {
+        let dead_letters: Vec<Value> = {
+            let mut dead_letters = self.dead_letters.lock().expect("Dead letters lock poisoned");
+            dead_letters.drain(..).collect()
+        };
+
+        // The dead-letter route already has these updates, so a failed redrive
+        // only keeps them here for the next one.
+        let mut results = Vec::with_capacity(dead_letters.len());
+        for update in dead_letters {
+            let result = self.deliver(&update).await;
+            if !result.is_delivered() {
+                keep(&self.dead_letters, update);
+            }
+            results.push(result);
+        }
+        results
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.url)
     }
 }
 
-impl Serverable for WebhookRoute {}
+#[async_trait]
+impl Serverable for WebhookRoute {
+    async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        match &self.dead_letter {
+            Some(dead_letter) => dead_letter.set_server(router).await,
+            None => router,
+        }
+    }
+}
 
 #[async_trait]
 impl Printable for WebhookRoute {
     async fn print(&self) -> String {
-        format!("webhook: {}", self.url)
+        match &self.dead_letter {
+            Some(dead_letter) => format!("webhook: {} (dead letters -> {})", self.url, dead_letter.print().await),
+            None => format!("webhook: {}", self.url),
+        }
     }
 
     async fn json_struct(&self) -> Value {
+        let dead_letter_json = match &self.dead_letter {
+            Some(dead_letter) => dead_letter.json_struct().await,
+            None => Value::Null,
+        };
+
         json!({
             "type": "webhook",
             "options": {
-                "url": self.url
-            }
+                "url": self.url,
+                "timeout": self.timeout.as_millis() as u64,
+                "max_attempts": self.retry.max_attempts,
+                "dead_letter": dead_letter_json
+            },
+            "dead_letters": self.dead_letters_count(),
+            "circuit": self.breaker.as_ref().map(|breaker| breaker.state_name()),
+            "healthy": self.is_healthy()
         })
     }
 }
@@ -55,6 +274,25 @@ mod tests {
     use super::*;
     use wiremock::{MockServer, Mock, ResponseTemplate};
     use wiremock::matchers::{method, body_json};
+    use crate::mock::routes::MockCallsRoute;
+    use crate::route::breaker::BreakerPolicy;
+    use crate::route::health::HealthPolicy;
+
+    fn fast_retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn route_for(server: &MockServer) -> WebhookRoute {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let mut route = WebhookRoute::new(server.uri());
+        route.client = client;
+        route
+    }
 
 
     
@@ -80,19 +318,331 @@ mod tests {
             .unwrap();
 
         let mut route = WebhookRoute::new(mock_server.uri());
-        route.set_client(client);
+        route.client = client;
 
-        route.process(payload).await;
-        
+        assert!(route.process(payload).await.is_delivered());
     }
 
 
+    #[tokio::test]
+    async fn test_process_reports_error_status() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let mut route = WebhookRoute::new(mock_server.uri());
+        route.client = client;
+
+        match route.process(json!({"update_id": 1})).await {
+            DeliveryResult::BackendError(reason) => assert!(reason.contains("503")),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
     #[tokio::test]
     async fn test_process_does_not_panic_on_network_error() {
         let route = WebhookRoute::new("http://localhost:9999/invalid".to_string());
         
         let payload = json!({"test": "data"});
-        route.process(payload).await;
+        assert!(!route.process(payload).await.is_delivered());
+    }
+
+    #[tokio::test]
+    async fn test_pending_counts_in_flight_requests() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(300)))
+            .mount(&mock_server)
+            .await;
+
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let mut route = WebhookRoute::new(mock_server.uri());
+        route.client = client;
+        let route = std::sync::Arc::new(route);
+
+        let route_clone = route.clone();
+        let handle = tokio::spawn(async move {
+            let _ = route_clone.process(json!({"update_id": 1})).await;
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(route.pending().await, 1);
+
+        handle.await.unwrap();
+        assert_eq!(route.pending().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_retry(fast_retry(3));
+
+        assert!(route.process(json!({"update_id": 1})).await.is_delivered());
+    }
+
+    #[tokio::test]
+    async fn test_hanging_backend_times_out_and_is_retried() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_timeout(Duration::from_millis(100));
+        route.set_retry(fast_retry(2));
+
+        let started = std::time::Instant::now();
+        assert!(matches!(route.process(json!({"update_id": 1})).await, DeliveryResult::Timeout(_)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(route.pending().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_status_is_not_retried() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_retry(fast_retry(5));
+
+        assert!(!route.process(json!({"update_id": 1})).await.is_delivered());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_honored() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_retry(RetryPolicy {
+            max_backoff: Duration::from_secs(5),
+            ..fast_retry(2)
+        });
+
+        let started = std::time::Instant::now();
+        assert!(route.process(json!({"update_id": 1})).await.is_delivered());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_go_to_dead_letter() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let dead_letter = Arc::new(MockCallsRoute::new("dlq"));
+        let mut route = route_for(&mock_server);
+        route.set_retry(fast_retry(3));
+        route.set_dead_letter(dead_letter.clone());
+
+        let payload = json!({"update_id": 7});
+        assert!(!route.process(payload.clone()).await.is_delivered());
+
+        assert_eq!(dead_letter.get_calls().await, vec![payload]);
+        assert_eq!(route.json_struct().await["dead_letters"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_redrive_delivers_dead_letters() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_dead_letter(Arc::new(MockCallsRoute::new("dlq")));
+
+        assert!(!route.process(json!({"update_id": 1})).await.is_delivered());
+
+        let results = route.redrive().await;
+        assert_eq!(results, vec![DeliveryResult::Delivered]);
+        assert_eq!(route.json_struct().await["dead_letters"], 0);
+        assert!(route.redrive().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_redrive_keeps_dead_letters_without_resending_them() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let dead_letter = Arc::new(MockCallsRoute::new("dlq"));
+        let mut route = route_for(&mock_server);
+        route.set_dead_letter(dead_letter.clone());
+
+        assert!(!route.process(json!({"update_id": 1})).await.is_delivered());
+        assert_eq!(dead_letter.count().await, 1);
+
+        let results = route.redrive().await;
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].is_delivered());
+        assert_eq!(dead_letter.count().await, 1);
+        assert_eq!(route.json_struct().await["dead_letters"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_delivered_by_standby_is_not_dead_lettered() {
+        use crate::lb::failover::FailoverLB;
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let dead_letter = Arc::new(MockCallsRoute::new("dlq"));
+        let mut primary = route_for(&mock_server);
+        primary.set_dead_letter(dead_letter.clone());
+        let primary = Arc::new(primary);
+        let standby = Arc::new(MockCallsRoute::new("standby"));
+        let lb = FailoverLB::new(vec![primary.clone(), standby.clone()], Duration::from_millis(0));
+
+        assert!(lb.process(json!({"update_id": 1})).await.is_delivered());
+        assert_eq!(standby.count().await, 1);
+        assert_eq!(dead_letter.count().await, 0);
+        assert!(primary.redrive().await.is_empty());
+
+        // nobody delivered it, so the primary's dead-letter queue gets it once
+        standby.set_failing(true);
+        assert!(!lb.process(json!({"update_id": 2})).await.is_delivered());
+        assert_eq!(dead_letter.get_calls().await, vec![json!({"update_id": 2})]);
+        assert_eq!(primary.json_struct().await["dead_letters"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_rejections_are_dead_lettered() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let dead_letter = Arc::new(MockCallsRoute::new("dlq"));
+        let mut route = route_for(&mock_server);
+        route.set_dead_letter(dead_letter.clone());
+        route.set_breaker(CircuitBreaker::new(BreakerPolicy {
+            failure_threshold: 1,
+            cooldown: Duration::from_secs(60),
+            ..BreakerPolicy::default()
+        }));
+
+        let _ = route.process(json!({"update_id": 1})).await;
+        assert!(route.process(json!({"update_id": 2})).await.is_rejected());
+
+        assert_eq!(dead_letter.get_calls().await, vec![json!({"update_id": 1}), json!({"update_id": 2})]);
+        assert_eq!(route.json_struct().await["dead_letters"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_rejects_until_probe_succeeds() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_breaker(CircuitBreaker::new(BreakerPolicy {
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(100),
+            ..BreakerPolicy::default()
+        }));
+
+        let _ = route.process(json!({"update_id": 1})).await;
+        let _ = route.process(json!({"update_id": 2})).await;
+
+        assert!(!route.is_available().await);
+        assert_eq!(route.json_struct().await["circuit"], "open");
+        assert!(route.process(json!({"update_id": 3})).await.is_rejected());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(route.is_available().await);
+        assert!(route.process(json!({"update_id": 4})).await.is_delivered());
+        assert_eq!(route.json_struct().await["circuit"], "closed");
+    }
+
+    #[tokio::test]
+    async fn test_failed_health_checks_make_route_unavailable() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let mut route = route_for(&mock_server);
+        route.set_health_check(HealthCheck::new(HealthPolicy {
+            url: format!("{}/health", mock_server.uri()),
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(200),
+            healthy_threshold: 1,
+            unhealthy_threshold: 2,
+        }));
+        assert!(route.is_available().await);
+
+        route.start_health_checks().await;
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(!route.is_available().await);
+        assert_eq!(route.json_struct().await["healthy"], false);
     }
 
     #[tokio::test]
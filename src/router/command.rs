@@ -0,0 +1,304 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult};
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+pub struct CommandBranch {
+    pub commands: Vec<String>,
+    pub callback_prefixes: Vec<String>,
+    pub inline_prefixes: Vec<String>,
+    pub route: Arc<dyn RouteableComponent>,
+}
+
+pub struct CommandRouter {
+    bot_username: Option<String>,
+    branches: Vec<CommandBranch>,
+    default: Option<Arc<dyn RouteableComponent>>,
+    commands: HashMap<String, usize>,
+}
+
+fn normalize_command(command: &str) -> String {
+    command.trim_start_matches('/').to_lowercase()
+}
+
+impl CommandRouter {
+    pub fn new(bot_username: Option<String>, branches: Vec<CommandBranch>, default: Option<Arc<dyn RouteableComponent>>) -> Self {
+        let mut commands = HashMap::new();
+        for (i, branch) in branches.iter().enumerate() {
+            for command in &branch.commands {
+                commands.entry(normalize_command(command)).or_insert(i);
+            }
+        }
+
+        Self {
+            bot_username: bot_username.map(|name| name.trim_start_matches('@').to_string()),
+            branches,
+            default,
+            commands,
+        }
+    }
+
+    fn match_command(&self, text: &str) -> Option<usize> {
+        let token = text.strip_prefix('/')?.split_whitespace().next()?;
+
+        let (command, username) = match token.split_once('@') {
+            Some((command, username)) => (command, Some(username)),
+            None => (token, None),
+        };
+
+        // An addressed command is only ours when we know our own name and it matches.
+        if let Some(username) = username {
+            if !self.bot_username.as_ref().is_some_and(|bot_username| username.eq_ignore_ascii_case(bot_username)) {
+                return None;
+            }
+        }
+
+        self.commands.get(&command.to_lowercase()).copied()
+    }
+
+    fn match_prefix(&self, data: &str, prefixes: fn(&CommandBranch) -> &Vec<String>) -> Option<usize> {
+        self.branches
+            .iter()
+            .position(|branch| prefixes(branch).iter().any(|prefix| data.starts_with(prefix.as_str())))
+    }
+
+    fn select(&self, update: &Value) -> Option<&Arc<dyn RouteableComponent>> {
+        let branch = if let Some(text) = update.pointer("/message/text").and_then(|t| t.as_str()) {
+            self.match_command(text)
+        } else if let Some(data) = update.pointer("/callback_query/data").and_then(|d| d.as_str()) {
+            self.match_prefix(data, |branch| &branch.callback_prefixes)
+        } else if let Some(query) = update.pointer("/inline_query/query").and_then(|q| q.as_str()) {
+            self.match_prefix(query, |branch| &branch.inline_prefixes)
+        } else {
+            None
+        };
+
+        branch
+            .map(|i| &self.branches[i].route)
+            .or(self.default.as_ref())
+    }
+}
+
+#[async_trait]
+impl Routeable for CommandRouter {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        match self.select(&update) {
+            Some(route) => route.process(update).await,
+            None => DeliveryResult::Ignored("no route for this command".to_string()),
+        }
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        match self.branches.get(index) {
+            Some(branch) => Some(branch.route.clone()),
+            None if index == self.branches.len() => self.default.clone(),
+            None => None,
+        }
+    }
+
+    async fn pending(&self) -> usize {
+        let mut pending = 0;
+        for branch in &self.branches {
+            pending += branch.route.pending().await;
+        }
+        if let Some(default) = &self.default {
+            pending += default.pending().await;
+        }
+        pending
+    }
+}
+
+#[async_trait]
+impl Serverable for CommandRouter {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        for branch in &self.branches {
+            router = branch.route.set_server(router).await;
+        }
+        if let Some(default) = &self.default {
+            router = default.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for CommandRouter {
+    async fn print(&self) -> String {
+        let mut text = String::from("ROUTER Command\n\n");
+
+        for branch in &self.branches {
+            let commands: Vec<String> = branch.commands.iter().map(|c| format!("/{}", normalize_command(c))).collect();
+            let callbacks: Vec<String> = branch.callback_prefixes.iter().map(|p| format!("callback:{}", p)).collect();
+            let inlines: Vec<String> = branch.inline_prefixes.iter().map(|p| format!("inline:{}", p)).collect();
+
+            let keys = [commands, callbacks, inlines].concat().join(", ");
+            text.push_str(&format!("[{}] {}\n\n", keys, branch.route.print().await));
+        }
+        if let Some(default) = &self.default {
+            text.push_str(&format!("[default] {}\n\n", default.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let mut routes_json: Vec<Value> = Vec::new();
+        for branch in &self.branches {
+            routes_json.push(json!({
+                "commands": branch.commands,
+                "callback_prefixes": branch.callback_prefixes,
+                "inline_prefixes": branch.inline_prefixes,
+                "route": branch.route.json_struct().await
+            }));
+        }
+
+        let default_json = match &self.default {
+            Some(default) => default.json_struct().await,
+            None => Value::Null,
+        };
+
+        json!({
+            "type": "router",
+            "name": "command",
+            "bot_username": self.bot_username,
+            "routes": routes_json,
+            "default": default_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn text(text: &str) -> Value {
+        json!({"update_id": 1, "message": {"chat": {"id": 1}, "text": text}})
+    }
+
+    struct Setup {
+        payments: Arc<MockCallsRoute>,
+        admin: Arc<MockCallsRoute>,
+        rest: Arc<MockCallsRoute>,
+        router: CommandRouter,
+    }
+
+    fn setup() -> Setup {
+        let payments = Arc::new(MockCallsRoute::new("payments"));
+        let admin = Arc::new(MockCallsRoute::new("admin"));
+        let rest = Arc::new(MockCallsRoute::new("rest"));
+
+        let router = CommandRouter::new(
+            Some("@MyBot".to_string()),
+            vec![
+                CommandBranch {
+                    commands: strings(&["/pay", "refund"]),
+                    callback_prefixes: strings(&["pay:"]),
+                    inline_prefixes: strings(&["invoice "]),
+                    route: payments.clone(),
+                },
+                CommandBranch {
+                    commands: strings(&["ban"]),
+                    callback_prefixes: strings(&["admin:"]),
+                    inline_prefixes: vec![],
+                    route: admin.clone(),
+                },
+            ],
+            Some(rest.clone()),
+        );
+
+        Setup { payments, admin, rest, router }
+    }
+
+    #[tokio::test]
+    async fn test_routes_commands() {
+        let s = setup();
+
+        let _ = s.router.process(text("/pay 100")).await;
+        let _ = s.router.process(text("/REFUND")).await;
+        let _ = s.router.process(text("/ban @spammer")).await;
+        let _ = s.router.process(text("/start")).await;
+        let _ = s.router.process(text("pay me")).await;
+
+        assert_eq!(s.payments.count().await, 2);
+        assert_eq!(s.admin.count().await, 1);
+        assert_eq!(s.rest.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_bot_username_suffix() {
+        let s = setup();
+
+        let _ = s.router.process(text("/pay@MyBot 100")).await;
+        let _ = s.router.process(text("/pay@mybot")).await;
+        let _ = s.router.process(text("/pay@OtherBot")).await;
+
+        assert_eq!(s.payments.count().await, 2);
+        assert_eq!(s.rest.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_callback_and_inline_prefixes() {
+        let s = setup();
+
+        let _ = s.router.process(json!({"update_id": 1, "callback_query": {"data": "pay:42"}})).await;
+        let _ = s.router.process(json!({"update_id": 2, "callback_query": {"data": "admin:ban:7"}})).await;
+        let _ = s.router.process(json!({"update_id": 3, "callback_query": {"data": "menu"}})).await;
+        let _ = s.router.process(json!({"update_id": 4, "inline_query": {"query": "invoice 12"}})).await;
+        let _ = s.router.process(json!({"update_id": 5, "inline_query": {"query": "pay:"}})).await;
+
+        assert_eq!(s.payments.count().await, 2);
+        assert_eq!(s.admin.count().await, 1);
+        assert_eq!(s.rest.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_without_default_is_dropped() {
+        let payments = Arc::new(MockCallsRoute::new("payments"));
+        let router = CommandRouter::new(
+            None,
+            vec![CommandBranch {
+                commands: strings(&["pay"]),
+                callback_prefixes: vec![],
+                inline_prefixes: vec![],
+                route: payments.clone(),
+            }],
+            None,
+        );
+
+        assert!(matches!(router.process(text("/start")).await, DeliveryResult::Ignored(_)));
+        // without bot_username a command addressed to any bot is not ours
+        assert!(matches!(router.process(text("/pay@AnyBot")).await, DeliveryResult::Ignored(_)));
+        assert!(router.process(text("/pay")).await.is_delivered());
+
+        assert_eq!(payments.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_json_structure() {
+        let s = setup();
+
+        let output = s.router.json_struct().await;
+
+        assert_eq!(output["type"], "router");
+        assert_eq!(output["name"], "command");
+        assert_eq!(output["bot_username"], "MyBot");
+        assert_eq!(output["routes"][0]["commands"][0], "/pay");
+        assert_eq!(output["routes"][1]["route"]["id"], "admin");
+        assert_eq!(output["default"]["id"], "rest");
+    }
+}
@@ -0,0 +1,3 @@
+
+pub mod updatetype;
+pub mod command;
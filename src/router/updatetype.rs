@@ -0,0 +1,225 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable, DeliveryResult};
+use crate::utils::update::update_type;
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+pub struct UpdateTypeBranch {
+    pub updates: Vec<String>,
+    pub route: Arc<dyn RouteableComponent>,
+}
+
+pub struct UpdateTypeRouter {
+    branches: Vec<UpdateTypeBranch>,
+    default: Option<Arc<dyn RouteableComponent>>,
+    index: HashMap<String, usize>,
+}
+
+impl UpdateTypeRouter {
+    pub fn new(branches: Vec<UpdateTypeBranch>, default: Option<Arc<dyn RouteableComponent>>) -> Self {
+        let mut index = HashMap::new();
+        for (i, branch) in branches.iter().enumerate() {
+            for kind in &branch.updates {
+                index.entry(kind.clone()).or_insert(i);
+            }
+        }
+
+        Self {
+            branches,
+            default,
+            index,
+        }
+    }
+
+    fn select(&self, update: &Value) -> Option<&Arc<dyn RouteableComponent>> {
+        update_type(update)
+            .and_then(|kind| self.index.get(kind))
+            .map(|i| &self.branches[*i].route)
+            .or(self.default.as_ref())
+    }
+}
+
+#[async_trait]
+impl Routeable for UpdateTypeRouter {
+    async fn process(&self, update: Value) -> DeliveryResult {
+        match self.select(&update) {
+            Some(route) => route.process(update).await,
+            None => DeliveryResult::Ignored("no route for this update type".to_string()),
+        }
+    }
+
+    async fn child(&self, index: usize) -> Option<Arc<dyn RouteableComponent>> {
+        match self.branches.get(index) {
+            Some(branch) => Some(branch.route.clone()),
+            None if index == self.branches.len() => self.default.clone(),
+            None => None,
+        }
+    }
+
+    async fn pending(&self) -> usize {
+        let mut pending = 0;
+        for branch in &self.branches {
+            pending += branch.route.pending().await;
+        }
+        if let Some(default) = &self.default {
+            pending += default.pending().await;
+        }
+        pending
+    }
+}
+
+#[async_trait]
+impl Serverable for UpdateTypeRouter {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        for branch in &self.branches {
+            router = branch.route.set_server(router).await;
+        }
+        if let Some(default) = &self.default {
+            router = default.set_server(router).await;
+        }
+        router
+    }
+}
+
+#[async_trait]
+impl Printable for UpdateTypeRouter {
+    async fn print(&self) -> String {
+        let mut text = String::from("ROUTER UpdateType\n\n");
+
+        for branch in &self.branches {
+            text.push_str(&format!("[{}] {}\n\n", branch.updates.join(", "), branch.route.print().await));
+        }
+        if let Some(default) = &self.default {
+            text.push_str(&format!("[default] {}\n\n", default.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let mut routes_json: Vec<Value> = Vec::new();
+        for branch in &self.branches {
+            routes_json.push(json!({
+                "updates": branch.updates,
+                "route": branch.route.json_struct().await
+            }));
+        }
+
+        let default_json = match &self.default {
+            Some(default) => default.json_struct().await,
+            None => Value::Null,
+        };
+
+        json!({
+            "type": "router",
+            "name": "update-type",
+            "routes": routes_json,
+            "default": default_json
+        })
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+    use crate::lb::roundrobin::RoundRobinLB;
+
+    fn branch(updates: &[&str], route: Arc<dyn RouteableComponent>) -> UpdateTypeBranch {
+        UpdateTypeBranch {
+            updates: updates.iter().map(|s| s.to_string()).collect(),
+            route,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_update_type() {
+        let inline = Arc::new(MockCallsRoute::new("inline"));
+        let members = Arc::new(MockCallsRoute::new("members"));
+        let rest = Arc::new(MockCallsRoute::new("rest"));
+
+        let router = UpdateTypeRouter::new(
+            vec![
+                branch(&["inline_query"], inline.clone()),
+                branch(&["chat_member", "my_chat_member"], members.clone()),
+            ],
+            Some(rest.clone()),
+        );
+
+        let _ = router.process(json!({"update_id": 1, "inline_query": {"query": "q"}})).await;
+        let _ = router.process(json!({"update_id": 2, "chat_member": {}})).await;
+        let _ = router.process(json!({"update_id": 3, "my_chat_member": {}})).await;
+        let _ = router.process(json!({"update_id": 4, "message": {"text": "hi"}})).await;
+
+        assert_eq!(inline.count().await, 1);
+        assert_eq!(members.count().await, 2);
+        assert_eq!(rest.count().await, 1);
+        assert_eq!(rest.get_calls().await[0]["update_id"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_without_default_is_dropped() {
+        let inline = Arc::new(MockCallsRoute::new("inline"));
+        let router = UpdateTypeRouter::new(vec![branch(&["inline_query"], inline.clone())], None);
+
+        assert!(matches!(router.process(json!({"update_id": 1, "message": {}})).await, DeliveryResult::Ignored(_)));
+        assert!(matches!(router.process(json!("garbage")).await, DeliveryResult::Ignored(_)));
+
+        assert_eq!(inline.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ignored_update_is_not_offered_to_a_sibling() {
+        let inline = Arc::new(MockCallsRoute::new("inline"));
+        let sibling = Arc::new(MockCallsRoute::new("sibling"));
+        let router: Arc<dyn RouteableComponent> =
+            Arc::new(UpdateTypeRouter::new(vec![branch(&["inline_query"], inline.clone())], None));
+        let lb = RoundRobinLB::new(vec![router, sibling.clone()]);
+
+        assert!(matches!(lb.process(json!({"update_id": 1, "message": {}})).await, DeliveryResult::Ignored(_)));
+        assert_eq!(sibling.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_first_branch_wins_on_duplicate_type() {
+        let first = Arc::new(MockCallsRoute::new("first"));
+        let second = Arc::new(MockCallsRoute::new("second"));
+        let router = UpdateTypeRouter::new(
+            vec![
+                branch(&["message"], first.clone()),
+                branch(&["message"], second.clone()),
+            ],
+            None,
+        );
+
+        let _ = router.process(json!({"update_id": 1, "message": {}})).await;
+
+        assert_eq!(first.count().await, 1);
+        assert_eq!(second.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_json_structure() {
+        let inline = Arc::new(MockCallsRoute::new("inline"));
+        let rest = Arc::new(MockCallsRoute::new("rest"));
+        let router = UpdateTypeRouter::new(vec![branch(&["inline_query"], inline)], Some(rest));
+
+        let output = router.json_struct().await;
+
+        assert_eq!(output["type"], "router");
+        assert_eq!(output["name"], "update-type");
+        assert_eq!(output["routes"][0]["updates"][0], "inline_query");
+        assert_eq!(output["routes"][0]["route"]["id"], "inline");
+        assert_eq!(output["default"]["id"], "rest");
+    }
+}
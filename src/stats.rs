@@ -0,0 +1,121 @@
+use crate::base::DeliveryResult;
+
+use serde_json::{json, Value};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Undelivered updates are logged at most this often, a failing backend would flood stderr otherwise.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct FailureReport {
+    last: Option<Instant>,
+    unreported: u64,
+}
+
+
+#[derive(Default)]
+pub struct DeliveryStats {
+    delivered: AtomicU64,
+    ignored: AtomicU64,
+    rejected: AtomicU64,
+    backend_error: AtomicU64,
+    timeout: AtomicU64,
+    duplicates: AtomicU64,
+    report: Mutex<FailureReport>,
+}
+
+impl DeliveryStats {
+    pub fn record(&self, result: &DeliveryResult) {
+        let counter = match result {
+            DeliveryResult::Delivered => &self.delivered,
+            DeliveryResult::Ignored(_) => &self.ignored,
+            DeliveryResult::Rejected(_) => &self.rejected,
+            DeliveryResult::BackendError(_) => &self.backend_error,
+            DeliveryResult::Timeout(_) => &self.timeout,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if !matches!(result, DeliveryResult::Delivered | DeliveryResult::Ignored(_)) {
+            if let Some(line) = self.report_failure(result) {
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    /// Sums up the undelivered updates since the last report once `REPORT_INTERVAL` has passed.
+    fn report_failure(&self, result: &DeliveryResult) -> Option<String> {
+        let mut report = self.report.lock().expect("Failure report lock poisoned");
+        report.unreported += 1;
+        if report.last.is_some_and(|last| last.elapsed() < REPORT_INTERVAL) {
+            return None;
+        }
+
+        let line = format!("Updates not delivered: {} since the last report, last one {}", report.unreported, result);
+        report.last = Some(Instant::now());
+        report.unreported = 0;
+        Some(line)
+    }
+
+    /// Counts an update that was dropped before routing because it was seen before.
+    pub fn record_duplicate(&self) {
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn json_struct(&self) -> Value {
+        json!({
+            "delivered": self.delivered.load(Ordering::Relaxed),
+            "ignored": self.ignored.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "backend_error": self.backend_error.load(Ordering::Relaxed),
+            "timeout": self.timeout.load(Ordering::Relaxed),
+            "duplicates": self.duplicates.load(Ordering::Relaxed),
+        })
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_each_outcome() {
+        let stats = DeliveryStats::default();
+
+        stats.record(&DeliveryResult::Delivered);
+        stats.record(&DeliveryResult::Delivered);
+        stats.record(&DeliveryResult::Rejected("full".to_string()));
+        stats.record(&DeliveryResult::BackendError("500".to_string()));
+        stats.record(&DeliveryResult::Timeout("slow".to_string()));
+        stats.record(&DeliveryResult::Ignored("no route".to_string()));
+
+        let json = stats.json_struct();
+        assert_eq!(json["delivered"], 2);
+        assert_eq!(json["ignored"], 1);
+        assert_eq!(json["rejected"], 1);
+        assert_eq!(json["backend_error"], 1);
+        assert_eq!(json["timeout"], 1);
+    }
+
+    #[test]
+    fn test_failures_are_reported_in_batches() {
+        let stats = DeliveryStats::default();
+        let full = DeliveryResult::Rejected("full".to_string());
+
+        assert_eq!(
+            stats.report_failure(&full).as_deref(),
+            Some("Updates not delivered: 1 since the last report, last one rejected: full"),
+        );
+        for _ in 0..5 {
+            assert_eq!(stats.report_failure(&full), None);
+        }
+
+        stats.report.lock().unwrap().last = Some(Instant::now() - REPORT_INTERVAL);
+        let line = stats.report_failure(&DeliveryResult::Timeout("slow".to_string())).unwrap();
+        assert_eq!(line, "Updates not delivered: 6 since the last report, last one timeout: slow");
+    }
+}
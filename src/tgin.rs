@@ -1,12 +1,23 @@
-use crate::base::{RouteableComponent, Serverable, UpdaterComponent};
+use crate::base::{RouteableComponent, Serverable, UpdaterComponent, RouteError, route_at, descendants};
 use crate::api::message::ApiMessage;
 use crate::api::router::Api;
+use crate::api::tree;
+use crate::stats::DeliveryStats;
+use crate::dedup::Dedup;
+use crate::ordering::Lanes;
+use crate::shutdown::{self, InFlight};
+use crate::config::reload::{Reloader, ProviderChange};
+use crate::utils::update::UpdateKey;
 
 use axum::Router;
-use serde_json::Value;
+use serde_json::{Value, json};
+use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
 
 use axum_server::tls_rustls::RustlsConfig;
 use std::net::SocketAddr;
@@ -14,10 +25,11 @@ use std::net::SocketAddr;
 use tokio::runtime::Builder;
 
 use crate::dynamic::handler::dynamic_handler;
+use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
 
 
 pub struct Tgin {
-    updates: Vec<Box<dyn UpdaterComponent>>,
+    updates: Vec<Arc<dyn UpdaterComponent>>,
     route: Arc<dyn RouteableComponent>,
     dark_threads: usize,
     server_port: Option<u16>,
@@ -26,6 +38,20 @@ pub struct Tgin {
     pub ssl_key: Option<String>,
 
     api: Option<Api>,
+
+    stats: Arc<DeliveryStats>,
+    dedup: Option<Arc<Dedup>>,
+    forwarders: Vec<JoinHandle<()>>,
+    ingress_stopped: watch::Sender<bool>,
+    ordered_by: Option<UpdateKey>,
+
+    in_flight: Arc<InFlight>,
+    limit: Arc<Semaphore>,
+    shutdown_timeout: Duration,
+    drain_window: Duration,
+
+    reloader: Option<Reloader>,
+    bound_paths: HashSet<String>,
 }
 
 impl Tgin {
@@ -36,13 +62,24 @@ impl Tgin {
         server_port: Option<u16>,
     ) -> Self {
         Self {
-            updates,
+            updates: updates.into_iter().map(Arc::from).collect(),
             route,
             dark_threads,
             server_port,
             ssl_cert: None,
             ssl_key: None,
-            api: None
+            api: None,
+            stats: Arc::new(DeliveryStats::default()),
+            dedup: None,
+            forwarders: Vec::new(),
+            ingress_stopped: watch::Sender::new(false),
+            ordered_by: None,
+            in_flight: Arc::new(InFlight::default()),
+            limit: Arc::new(Semaphore::new(10000)),
+            shutdown_timeout: Duration::from_secs(5),
+            drain_window: Duration::from_secs(3),
+            reloader: None,
+            bound_paths: HashSet::new(),
         }
     }
 
@@ -50,6 +87,33 @@ impl Tgin {
         self.api = Some(api);
     }
 
+    pub fn set_dedup(&mut self, dedup: Dedup) {
+        self.dedup = Some(Arc::new(dedup));
+    }
+
+    /// Delivers updates with the same key one after another, see [`Lanes`].
+    pub fn set_ordered_by(&mut self, key: UpdateKey) {
+        self.ordered_by = Some(key);
+    }
+
+    /// `timeout` bounds stopping the providers and finishing running deliveries, `drain_window`
+    /// is how long long-poll consumers get afterwards to empty their buffers.
+    pub fn set_shutdown(&mut self, timeout: Duration, drain_window: Duration) {
+        self.shutdown_timeout = timeout;
+        self.drain_window = drain_window;
+    }
+
+    /// At most `max_in_flight` updates are in the route tree at once. Further updates wait in the
+    /// providers, so a long-poll buffer that blocks holds back ingress instead of piling up tasks.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.limit = Arc::new(Semaphore::new(max_in_flight.max(1)));
+    }
+
+    /// Reloads the config on SIGHUP, see [`Reloader`].
+    pub fn set_reloader(&mut self, reloader: Reloader) {
+        self.reloader = Some(reloader);
+    }
+
     pub fn set_ssl(&mut self, ssl_cert: String, ssl_key: String) {
         self.ssl_cert = Some(ssl_cert);
         self.ssl_key = Some(ssl_key);
@@ -81,12 +145,308 @@ impl Tgin {
 
 
 
-    pub async fn run_async(self) {
-        let (tx, mut rx) = mpsc::channel::<Value>(1000000);
+    /// Waits for a free delivery slot, then for the next update. Nothing is lost when the
+    /// future is dropped early.
+    async fn next_update(limit: &Arc<Semaphore>, rx: &mut Receiver<Value>) -> Option<(OwnedSemaphorePermit, Value)> {
+        let permit = limit.clone().acquire_owned().await.expect("Delivery limit is never closed");
+        rx.recv().await.map(|update| (permit, update))
+    }
+
+    fn dispatch(&self, lanes: Option<&Lanes>, permit: OwnedSemaphorePermit, update: Value) {
+        if let Some(lanes) = lanes {
+            lanes.push(self.route.clone(), permit, update);
+            return;
+        }
+        let route = self.route.clone();
+        let stats = self.stats.clone();
+        let guard = self.in_flight.track();
+        tokio::spawn(async move {
+            let result = route.process(update).await;
+            stats.record(&result);
+            drop(guard);
+            drop(permit);
+        });
+    }
+
+    /// Stops ingress, lets running deliveries finish and gives long-poll consumers
+    /// `drain_window` to collect what is left in their buffers.
+    async fn shutdown(&mut self, rx: &mut Receiver<Value>, lanes: Option<&Lanes>) {
+        println!("Shutting down, stopping ingress");
+        let forwarders = std::mem::take(&mut self.forwarders);
+        let stop = Self::stop_ingress(&self.updates, &self.ingress_stopped, forwarders, self.shutdown_timeout);
+        tokio::pin!(stop);
+
+        // Providers blocked on a full channel could not stop, so updates keep being taken meanwhile.
+        loop {
+            tokio::select! {
+                _ = &mut stop => break,
+                Some((permit, update)) = Self::next_update(&self.limit, rx) => self.dispatch(lanes, permit, update),
+            }
+        }
+
+        // Whatever the providers handed over before they stopped is still delivered.
+        while let Ok(update) = rx.try_recv() {
+            match timeout(self.shutdown_timeout, self.limit.clone().acquire_owned()).await {
+                Ok(permit) => self.dispatch(lanes, permit.expect("Delivery limit is never closed"), update),
+                Err(_) => {
+                    eprintln!("No delivery slot freed up within {:?}, {} updates not delivered", self.shutdown_timeout, rx.len() + 1);
+                    break;
+                }
+            }
+        }
+
+        println!("Waiting for {} deliveries in flight", self.in_flight.count());
+        if timeout(self.shutdown_timeout, self.in_flight.wait_idle()).await.is_err() {
+            eprintln!("{} deliveries still running after {:?}, giving up on them", self.in_flight.count(), self.shutdown_timeout);
+        }
+
+        let deadline = Instant::now() + self.drain_window;
+        loop {
+            let buffered = Self::buffered(&self.route).await;
+            if buffered == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                eprintln!("{} updates left in long-poll buffers after the drain window", buffered);
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        println!("Shutdown complete");
+    }
+
+    /// Stops the providers, then lets the deduplication forwarders pass on what they still hold.
+    async fn stop_ingress(
+        updates: &[Arc<dyn UpdaterComponent>],
+        ingress_stopped: &watch::Sender<bool>,
+        forwarders: Vec<JoinHandle<()>>,
+        stop_timeout: Duration,
+    ) {
+        for provider in updates {
+            if timeout(stop_timeout, provider.stop()).await.is_err() {
+                eprintln!("{} did not stop within {:?}", provider.print().await, stop_timeout);
+            }
+        }
+
+        ingress_stopped.send_replace(true);
+        for forwarder in forwarders {
+            if timeout(stop_timeout, forwarder).await.is_err() {
+                eprintln!("Deduplication did not pass on its updates within {:?}", stop_timeout);
+            }
+        }
+    }
+
+    /// Updates waiting in the leaves of the route tree, i.e. in long-poll buffers once
+    /// webhook deliveries are done.
+    async fn buffered(route: &Arc<dyn RouteableComponent>) -> usize {
+        let mut buffered = 0;
+        for node in descendants(route).await {
+            if node.child(0).await.is_none() {
+                buffered += node.pending().await;
+            }
+        }
+        buffered
+    }
+
+    /// Gives a provider its own channel and forwards only updates not seen before. Once
+    /// ingress has stopped, the forwarder passes on what is left in the channel and ends.
+    fn deduplicate(
+        dedup: Arc<Dedup>,
+        stats: Arc<DeliveryStats>,
+        bot: String,
+        tx: Sender<Value>,
+        mut ingress_stopped: watch::Receiver<bool>,
+    ) -> (Sender<Value>, JoinHandle<()>) {
+        let (provider_tx, mut provider_rx) = mpsc::channel::<Value>(1000);
+        let forwarder = tokio::spawn(async move {
+            let mut closed = false;
+            loop {
+                let update = tokio::select! {
+                    update = provider_rx.recv() => update,
+                    _ = ingress_stopped.wait_for(|stopped| *stopped), if !closed => {
+                        provider_rx.close();
+                        closed = true;
+                        continue;
+                    }
+                };
+                let Some(update) = update else {
+                    break;
+                };
+                if !dedup.is_new(&bot, &update) {
+                    stats.record_duplicate();
+                    continue;
+                }
+                if tx.send(update).await.is_err() {
+                    break;
+                }
+            }
+        });
+        (provider_tx, forwarder)
+    }
+
+    fn provider_tx(&mut self, i: usize, provider: &Arc<dyn UpdaterComponent>, tx: &Sender<Value>) -> Sender<Value> {
+        match &self.dedup {
+            Some(dedup) => {
+                let bot = provider.bot().unwrap_or_else(|| format!("provider-{}", i));
+                let (provider_tx, forwarder) = Self::deduplicate(
+                    dedup.clone(),
+                    self.stats.clone(),
+                    bot,
+                    tx.clone(),
+                    self.ingress_stopped.subscribe(),
+                );
+                self.forwarders.push(forwarder);
+                provider_tx
+            }
+            None => tx.clone(),
+        }
+    }
 
-        let api = self.api;
+    async fn reload_requested(reloader: &mut Option<(Reloader, Sender<Value>)>) {
+        match reloader {
+            Some((reloader, _)) => reloader.requested().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Swaps in the route tree and providers of the reloaded config. Updates dispatched
+    /// before finish on the tree they were dispatched to.
+    async fn reload(&mut self, reloader: &mut Reloader, tx: &Sender<Value>) {
+        let reload = match reloader.reload() {
+            Ok(reload) => reload,
+            Err(err) => {
+                eprintln!("Failed to reload {}, keeping the running config: {}", reloader.path(), err);
+                return;
+            }
+        };
+
+        Self::activate(&reload.route).await;
+        let previous = std::mem::replace(&mut self.route, reload.route);
+        self.release(&previous).await;
+
+        let running = std::mem::take(&mut self.updates);
+        self.forwarders.retain(|forwarder| !forwarder.is_finished());
+        let mut kept = vec![false; running.len()];
+        let mut starting = Vec::new();
+        for change in reload.updates {
+            let provider = match change {
+                ProviderChange::Keep(i) => {
+                    kept[i] = true;
+                    running[i].clone()
+                }
+                ProviderChange::Start(provider) => {
+                    let provider: Arc<dyn UpdaterComponent> = Arc::from(provider);
+                    let provider_tx = self.provider_tx(self.updates.len(), &provider, tx);
+                    provider.attach(provider_tx.clone());
+                    starting.push((provider.clone(), provider_tx));
+                    provider
+                }
+            };
+            self.updates.push(provider);
+        }
+        let stopping: Vec<Arc<dyn UpdaterComponent>> = running.into_iter().zip(kept)
+            .filter(|(_, kept)| !kept)
+            .map(|(provider, _)| provider)
+            .collect();
+
+        // A restarted long-poll provider has to be gone before its successor polls with the same token.
+        let stop_timeout = self.shutdown_timeout;
+        tokio::spawn(async move {
+            for provider in stopping {
+                if timeout(stop_timeout, provider.stop()).await.is_err() {
+                    eprintln!("{} did not stop within {:?}", provider.print().await, stop_timeout);
+                }
+            }
+            for (provider, tx) in starting {
+                tokio::spawn(async move {
+                    provider.start(tx).await;
+                });
+            }
+        });
+
+        println!("RELOADED {}, RUTE TO\n\n{}", reloader.path(), self.route.print().await);
+    }
+
+    /// Starts the health checks of a route entering the tree and serves its long-poll paths.
+    async fn activate(route: &Arc<dyn RouteableComponent>) {
+        for node in descendants(route).await {
+            node.start_health_checks().await;
+            node.register();
+        }
+    }
+
+    /// Stops serving the long-poll paths of a route removed through the api or by a reload, unless
+    /// the tree still has them or they were bound to the server at startup. Updates left in their
+    /// buffers are reported, no consumer gets them any more.
+    async fn release(&self, removed: &Arc<dyn RouteableComponent>) {
+        let live = tree::poll_paths(&self.route).await;
+        for node in descendants(removed).await {
+            let Some(path) = node.poll_path() else {
+                continue;
+            };
+            if live.iter().any(|live| live == path) {
+                continue;
+            }
+            let buffered = node.pending().await;
+            if self.bound_paths.contains(path) {
+                if buffered > 0 {
+                    eprintln!("Long-poll route {} left the tree with {} updates, it is served until a restart", path, buffered);
+                }
+                continue;
+            }
+            LONGPOLL_REGISTRY.write().expect("Registry lock poisoned").remove(path);
+            if buffered > 0 {
+                eprintln!("Stopped serving long-poll route {}, {} buffered updates are dropped", path, buffered);
+            }
+        }
+    }
+
+    fn redrive(route: &Arc<dyn RouteableComponent>, stats: &Arc<DeliveryStats>, response: oneshot::Sender<Value>) {
+        let route = route.clone();
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            let mut redriven = 0;
+            let mut delivered = 0;
+            for node in descendants(&route).await {
+                for result in node.redrive().await {
+                    stats.record(&result);
+                    redriven += 1;
+                    if result.is_delivered() {
+                        delivered += 1;
+                    }
+                }
+            }
+            let _ = response.send(json!({
+                "redriven": redriven,
+                "delivered": delivered,
+            }));
+        });
+    }
+
+    pub async fn run_async(mut self) {
+        // Small on purpose, a full channel makes the providers wait.
+        let (tx, mut rx) = mpsc::channel::<Value>(1000);
+        let limit = self.limit.clone();
+
+        Self::activate(&self.route).await;
+
+        let api = self.api.take();
+        let lanes = self.ordered_by.take()
+            .map(|key| Lanes::new(key, self.stats.clone(), self.in_flight.clone()));
+        let mut reloader = self.reloader.take().map(|reloader| (reloader, tx.clone()));
+
+        // Deduplication is in place before the server below takes the first webhook update.
+        let providers = self.updates.clone();
+        let provider_txs: Vec<Sender<Value>> = providers.iter().enumerate()
+            .map(|(i, provider)| {
+                let provider_tx = self.provider_tx(i, provider, &tx);
+                provider.attach(provider_tx.clone());
+                provider_tx
+            })
+            .collect();
 
         if let Some(port) = self.server_port {
+            self.bound_paths = tree::poll_paths(&self.route).await.into_iter().collect();
             let mut router: Router<Sender<Value>> = Router::new();
 
             for provider in &self.updates {
@@ -102,7 +462,8 @@ impl Tgin {
             
             let app = router.with_state(tx.clone());
 
-            let app = if api.is_some() {
+            // Serves long-poll routes added through the api or by a reload, other paths get a 404.
+            let app = if api.is_some() || reloader.is_some() {
                 app.fallback(dynamic_handler)
             } else {
                 app
@@ -118,7 +479,7 @@ impl Tgin {
 
                     tokio::spawn(async move {
                         axum_server::bind_rustls(addr, config)
-                            .serve(app.into_make_service())
+                            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                             .await
                             .unwrap();
                     });
@@ -126,30 +487,39 @@ impl Tgin {
                 _ => {
                     tokio::spawn(async move {
                         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-                        axum::serve(listener, app).await.unwrap();
+                        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
                     });
                 }
             }
         }
 
-        for provider in self.updates {
-            let tx_clone = tx.clone();
+        for (provider, provider_tx) in self.updates.iter().zip(provider_txs) {
+            let provider = provider.clone();
             tokio::spawn(async move {
-                provider.start(tx_clone).await;
+                provider.start(provider_tx).await;
             });
         }
 
         drop(tx);
 
-
+        let signal = shutdown::signal();
+        tokio::pin!(signal);
 
         match api {
             None => {
-                while let Some(update) = rx.recv().await {
-                    let route_clone = self.route.clone();
-                    tokio::spawn(async move {
-                        route_clone.process(update).await;
-                    });
+                loop {
+                    tokio::select! {
+                        _ = &mut signal => break,
+                        _ = Self::reload_requested(&mut reloader) => {
+                            if let Some((reloader, tx)) = reloader.as_mut() {
+                                self.reload(reloader, tx).await;
+                            }
+                        }
+                        update = Self::next_update(&limit, &mut rx) => match update {
+                            Some((permit, update)) => self.dispatch(lanes.as_ref(), permit, update),
+                            None => break,
+                        },
+                    }
                 }
             },
 
@@ -164,31 +534,230 @@ impl Tgin {
                                     let _ = tx_response.send(self.route.json_struct().await);
                                 }
 
-                                ApiMessage::AddRoute{route, sublevel} => {
-                                    let _ = sublevel;
-                                    let self_route = self.route.clone();
-                                    match self_route.add_route(route).await {
-                                        Err(_) => {},
-                                        Ok(_) => {}
+                                ApiMessage::AddRoute{parent, index, weight, route, response} => {
+                                    let result = tree::insert(&self.route, &parent, index, weight, route.clone()).await;
+                                    if result.is_ok() {
+                                        Self::activate(&route).await;
+                                    }
+                                    let _ = response.send(result);
+                                }
+
+                                ApiMessage::RemoveRoute{path, response} => {
+                                    let result = match tree::remove(&self.route, &path).await {
+                                        Ok(removed) => {
+                                            self.release(&removed).await;
+                                            Ok(())
+                                        }
+                                        Err(err) => Err(err),
+                                    };
+                                    let _ = response.send(result);
+                                }
+
+                                ApiMessage::UpdateRoute{path, route, parent, index, weight, response} => {
+                                    let result = match tree::update(&mut self.route, &path, route.clone(), parent.as_deref(), index, weight).await {
+                                        Ok(replaced) => {
+                                            if let Some(route) = &route {
+                                                Self::activate(route).await;
+                                            }
+                                            if let Some(replaced) = replaced {
+                                                self.release(&replaced).await;
+                                            }
+                                            Ok(())
+                                        }
+                                        Err(err) => Err(err),
+                                    };
+                                    let _ = response.send(result);
+                                }
+
+                                ApiMessage::SetWeight{path, weight, response} => {
+                                    let result = match path.split_last() {
+                                        Some((index, parent)) => match route_at(&self.route, parent).await {
+                                            Some(parent) => parent.set_weight(*index, weight).await,
+                                            None => Err(RouteError::NotFound),
+                                        },
+                                        None => Err(RouteError::NotFound),
+                                    };
+                                    let _ = response.send(result);
+                                }
+
+                                ApiMessage::GetStats(tx_response) => {
+                                    let _ = tx_response.send(self.stats.json_struct());
+                                }
+
+                                ApiMessage::GetUpdates(tx_response) => {
+                                    let mut updates = Vec::with_capacity(self.updates.len());
+                                    for provider in &self.updates {
+                                        updates.push(provider.json_struct().await);
                                     }
+                                    let _ = tx_response.send(Value::Array(updates));
+                                }
+
+                                ApiMessage::Redrive(tx_response) => {
+                                    Self::redrive(&self.route, &self.stats, tx_response);
                                 }
                             }
                         },
 
-                        Some(update) = rx.recv() => {
-                            let route_clone = self.route.clone();
-                            tokio::spawn(async move {
-                                route_clone.process(update).await;
-                            });
+                        Some((permit, update)) = Self::next_update(&limit, &mut rx) => {
+                            self.dispatch(lanes.as_ref(), permit, update);
                         }
 
+                        _ = Self::reload_requested(&mut reloader) => {
+                            if let Some((reloader, tx)) = reloader.as_mut() {
+                                self.reload(reloader, tx).await;
+                            }
+                        }
+
+                        _ = &mut signal => break,
+
                     }
                 }
 
             }
         }
+
+        tokio::select! {
+            _ = self.shutdown(&mut rx, lanes.as_ref()) => {}
+            _ = shutdown::signal() => eprintln!("Second signal, exiting without draining"),
+        }
+    }
+
+
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::routes::MockCallsRoute;
+    use crate::lb::roundrobin::RoundRobinLB;
+    use crate::route::longpull::LongPollRoute;
+
+    #[tokio::test]
+    async fn test_duplicates_from_several_providers_are_dropped() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let dedup = Arc::new(Dedup::new(Duration::from_secs(60), 100));
+        let stats = Arc::new(DeliveryStats::default());
+
+        let (_, ingress_stopped) = watch::channel(false);
+        let (longpoll, _) = Tgin::deduplicate(dedup.clone(), stats.clone(), "123".to_string(), tx.clone(), ingress_stopped.clone());
+        let (webhook, _) = Tgin::deduplicate(dedup.clone(), stats.clone(), "123".to_string(), tx.clone(), ingress_stopped.clone());
+        let (other_bot, _) = Tgin::deduplicate(dedup, stats.clone(), "456".to_string(), tx, ingress_stopped);
+
+        longpoll.send(json!({"update_id": 1, "message": {}})).await.unwrap();
+        webhook.send(json!({"update_id": 1, "message": {}})).await.unwrap();
+        webhook.send(json!({"update_id": 1, "message": {}})).await.unwrap();
+        other_bot.send(json!({"update_id": 1, "message": {}})).await.unwrap();
+        drop((longpoll, webhook, other_bot));
+
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        assert_eq!(received, 2);
+        assert_eq!(stats.json_struct()["duplicates"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_updates_wait_for_a_free_delivery_slot() {
+        let route = Arc::new(MockCallsRoute::new("slow"));
+        route.set_delay(1, Duration::from_millis(100));
+        route.set_delay(2, Duration::from_secs(10));
+        let mut tgin = Tgin::new(vec![], route.clone(), 1, None);
+        tgin.set_max_in_flight(2);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        for id in 1..=3 {
+            tx.send(json!({"update_id": id})).await.unwrap();
+        }
+        for _ in 0..2 {
+            let (permit, update) = Tgin::next_update(&tgin.limit, &mut rx).await.unwrap();
+            tgin.dispatch(None, permit, update);
+        }
+
+        // the third one stays in the channel until the first delivery is done
+        assert!(timeout(Duration::from_millis(50), Tgin::next_update(&tgin.limit, &mut rx)).await.is_err());
+        assert_eq!(rx.len(), 1);
+
+        let (permit, update) = timeout(Duration::from_millis(200), Tgin::next_update(&tgin.limit, &mut rx)).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 3);
+        tgin.dispatch(None, permit, update);
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(route.count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_removed_dynamic_long_poll_paths_are_unregistered() {
+        let mut tgin = Tgin::new(vec![], Arc::new(RoundRobinLB::new(vec![])), 1, None);
+        tgin.bound_paths.insert("/tgin/bound".to_string());
+        let registered = |path: &str| LONGPOLL_REGISTRY.read().unwrap().contains_key(path);
+
+        for path in ["/tgin/dynamic", "/tgin/bound"] {
+            let route: Arc<dyn RouteableComponent> = Arc::new(LongPollRoute::new(path.to_string()));
+            tree::insert(&tgin.route, &[], None, None, route.clone()).await.unwrap();
+            Tgin::activate(&route).await;
+            assert!(registered(path));
+        }
+
+        for _ in 0..2 {
+            let removed = tree::remove(&tgin.route, &[0]).await.unwrap();
+            tgin.release(&removed).await;
+        }
+        assert!(!registered("/tgin/dynamic"));
+        // bound to the server at startup, the path keeps being served
+        assert!(registered("/tgin/bound"));
     }
 
+    #[tokio::test]
+    async fn test_shutdown_finishes_deliveries_and_waits_for_buffers() {
+        let route = Arc::new(MockCallsRoute::new("bot"));
+        route.set_delay(1, Duration::from_millis(100));
+        let mut tgin = Tgin::new(vec![], route.clone(), 1, None);
+        tgin.set_shutdown(Duration::from_secs(1), Duration::from_millis(200));
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let permit = tgin.limit.clone().acquire_owned().await.unwrap();
+        tgin.dispatch(None, permit, json!({"update_id": 1, "message": {}}));
+        tx.send(json!({"update_id": 2, "message": {}})).await.unwrap();
+
+        // a consumer empties the buffer during the drain window
+        route.set_pending(3);
+        let consumer = route.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(150)).await;
+            consumer.set_pending(0);
+        });
 
+        let started = Instant::now();
+        tgin.shutdown(&mut rx, None).await;
 
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert!(started.elapsed() < Duration::from_millis(200 + 100 + 150));
+        assert_eq!(route.count().await, 2);
+        assert_eq!(tgin.stats.json_struct()["delivered"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_takes_updates_stuck_behind_a_full_channel() {
+        let route = Arc::new(MockCallsRoute::new("bot"));
+        let mut tgin = Tgin::new(vec![], route.clone(), 1, None);
+        tgin.set_shutdown(Duration::from_secs(1), Duration::from_millis(0));
+
+        // the channel is full, so the forwarder is stuck with the next update
+        let (tx, mut rx) = mpsc::channel(1);
+        tx.send(json!({"update_id": 1, "message": {}})).await.unwrap();
+        let dedup = Arc::new(Dedup::new(Duration::from_secs(60), 100));
+        let (provider_tx, forwarder) = Tgin::deduplicate(dedup, tgin.stats.clone(), "123".to_string(), tx, tgin.ingress_stopped.subscribe());
+        tgin.forwarders.push(forwarder);
+        for id in 2..=3 {
+            provider_tx.send(json!({"update_id": id, "message": {}})).await.unwrap();
+        }
+        sleep(Duration::from_millis(20)).await;
+
+        timeout(Duration::from_millis(500), tgin.shutdown(&mut rx, None)).await.expect("shutdown waited for the full channel");
+        assert_eq!(route.count().await, 3);
+        assert_eq!(tgin.stats.json_struct()["delivered"], 3);
+    }
 }
@@ -0,0 +1,129 @@
+use crate::utils::cidr::Cidr;
+use crate::utils::defaults::TELEGRAM_WEBHOOK_RANGES;
+
+use axum::http::HeaderMap;
+
+use std::net::IpAddr;
+
+
+/// Name of the built-in preset that expands to Telegram's webhook ranges.
+pub const TELEGRAM_PRESET: &str = "telegram";
+
+/// A reverse proxy in front of tgin that reports the client address in `header`.
+/// The header is only believed when the request comes from one of `proxies`.
+pub struct TrustedProxy {
+    pub header: String,
+    pub proxies: Vec<Cidr>,
+}
+
+/// Source-address allowlist of a webhook endpoint.
+pub struct IpAllowlist {
+    ranges: Vec<Cidr>,
+    proxy: Option<TrustedProxy>,
+}
+
+pub fn parse_ranges(entries: &[String]) -> Result<Vec<Cidr>, String> {
+    let mut ranges = Vec::new();
+    for entry in entries {
+        if entry == TELEGRAM_PRESET {
+            ranges.extend(TELEGRAM_WEBHOOK_RANGES.iter().map(|range| range.parse::<Cidr>()).collect::<Result<Vec<_>, _>>()?);
+        } else {
+            ranges.push(entry.parse()?);
+        }
+    }
+    Ok(ranges)
+}
+
+impl IpAllowlist {
+    pub fn new(ranges: Vec<Cidr>) -> Self {
+        Self { ranges, proxy: None }
+    }
+
+    pub fn set_trusted_proxy(&mut self, proxy: TrustedProxy) {
+        self.proxy = Some(proxy);
+    }
+
+    fn is_proxy(&self, ip: IpAddr) -> bool {
+        self.proxy.as_ref().is_some_and(|proxy| proxy.proxies.iter().any(|net| net.contains(ip)))
+    }
+
+    /// Address of the client that sent the request. Behind trusted proxies the header is read
+    /// right to left, every hop appends to it, so the first address that is not a trusted
+    /// proxy itself is the one that connected to the outermost proxy.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let Some(proxy) = self.proxy.as_ref().filter(|_| self.is_proxy(peer)) else {
+            return peer;
+        };
+
+        let hops: Vec<IpAddr> = headers.get_all(proxy.header.as_str()).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            client = hop;
+            if !self.is_proxy(hop) {
+                break;
+            }
+        }
+        client
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|net| net.contains(ip))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn allowlist(entries: &[&str]) -> IpAllowlist {
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        IpAllowlist::new(parse_ranges(&entries).unwrap())
+    }
+
+    #[test]
+    fn test_telegram_preset() {
+        let allowlist = allowlist(&["telegram", "10.1.2.3"]);
+        assert!(allowlist.allows(ip("149.154.167.220")));
+        assert!(allowlist.allows(ip("91.108.6.1")));
+        assert!(allowlist.allows(ip("10.1.2.3")));
+        assert!(!allowlist.allows(ip("91.108.8.1")));
+        assert!(!allowlist.allows(ip("1.1.1.1")));
+
+        assert!(parse_ranges(&["telegramm".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_header_is_only_trusted_from_proxies() {
+        let mut allowlist = allowlist(&["telegram"]);
+        allowlist.set_trusted_proxy(TrustedProxy {
+            header: "x-forwarded-for".to_string(),
+            proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "149.154.167.1, 10.0.0.7".parse().unwrap());
+
+        // proxy chain: the client is the first untrusted hop from the right
+        assert_eq!(allowlist.client_ip(ip("10.0.0.2"), &headers), ip("149.154.167.1"));
+        // a forged header from an untrusted peer is ignored
+        assert_eq!(allowlist.client_ip(ip("1.2.3.4"), &headers), ip("1.2.3.4"));
+
+        // a client cannot hide behind a spoofed leftmost entry
+        headers.insert("x-forwarded-for", "149.154.167.1, 1.2.3.4".parse().unwrap());
+        assert_eq!(allowlist.client_ip(ip("10.0.0.2"), &headers), ip("1.2.3.4"));
+
+        // no header: the proxy itself
+        assert_eq!(allowlist.client_ip(ip("10.0.0.2"), &HeaderMap::new()), ip("10.0.0.2"));
+    }
+}
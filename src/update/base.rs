@@ -1,12 +1,48 @@
-
-
 use async_trait::async_trait;
 use serde_json::Value;
 use tokio::sync::mpsc::Sender;
 
+use std::fmt;
+
 #[async_trait]
 pub trait Updater: Send + Sync {
     async fn start(&self, tx: Sender<Value>);
 
+    /// Hands over the channel of this provider before the HTTP server accepts traffic,
+    /// so updates received over HTTP go through the same deduplication as the rest.
+    fn attach(&self, _tx: Sender<Value>) {}
+
+    /// Called once when tgin shuts down. No update may be handed over after it returns.
+    async fn stop(&self) {}
+
+    /// Bot the updates belong to, used to tell apart `update_id`s of different bots.
+    fn bot(&self) -> Option<String> {
+        None
+    }
 }
 
+/// Numeric bot id, the part of a token before the colon.
+pub fn bot_id(token: &str) -> String {
+    token.split(':').next().unwrap_or(token).to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdaterStatus {
+    Starting,
+    Running,
+    Retrying(String),
+    Fatal(String),
+    Stopped,
+}
+
+impl fmt::Display for UpdaterStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdaterStatus::Starting => write!(f, "starting"),
+            UpdaterStatus::Running => write!(f, "running"),
+            UpdaterStatus::Retrying(reason) => write!(f, "retrying: {}", reason),
+            UpdaterStatus::Fatal(reason) => write!(f, "fatal: {}", reason),
+            UpdaterStatus::Stopped => write!(f, "stopped"),
+        }
+    }
+}
@@ -1,22 +1,61 @@
 use crate::base::{Serverable, Printable};
-use crate::update::base::Updater;
+use crate::update::base::{Updater, UpdaterStatus, bot_id};
+use crate::update::offset::OffsetStore;
 use crate::utils::defaults::TELEGRAM_TOKEN_REGEX;
+use crate::utils::update::update_id;
 
 use async_trait::async_trait;
 use reqwest::Client;
-use serde_json::Value;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
 use tokio::time::{sleep, Duration};
 
 use regex::Regex;
 
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+
+/// `deleteWebhook` calls in a row before a conflict is treated like any other API error.
+const MAX_WEBHOOK_DELETIONS: u32 = 3;
+
 
 pub struct LongPollUpdate {
     client: Client,
     url: String,
+    bot: String,
     default_timeout_sleep: u64,
     error_timeout_sleep: u64,
     token_regex: Regex,
+    offset_file: Option<PathBuf>,
+    delete_webhook_on_conflict: bool,
+    webhook_deletions: AtomicU32,
+    status: Mutex<UpdaterStatus>,
+    poll_timeout: u64,
+    limit: u64,
+    allowed_updates: Option<Vec<String>>,
+    offset: AtomicI64,
+    stopping: watch::Sender<bool>,
+    running: tokio::sync::Mutex<()>,
+}
+
+/// Error part of a Bot API response (`ok: false`).
+struct ApiError {
+    code: i64,
+    description: String,
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    fn from_response(status: StatusCode, json: &Value) -> Self {
+        Self {
+            code: json.get("error_code").and_then(Value::as_i64).unwrap_or(status.as_u16() as i64),
+            description: json.get("description").and_then(Value::as_str).unwrap_or("no description").to_string(),
+            retry_after: json.pointer("/parameters/retry_after").and_then(Value::as_u64),
+        }
+    }
 }
 
 impl LongPollUpdate {
@@ -24,16 +63,23 @@ impl LongPollUpdate {
         Self {
             client: Client::new(),
             url: format!("https://api.telegram.org/bot{}/getUpdates", token),
+            bot: bot_id(&token),
             default_timeout_sleep: 0,
             error_timeout_sleep: 100,
             token_regex: Regex::new(TELEGRAM_TOKEN_REGEX).unwrap(),
+            offset_file: None,
+            delete_webhook_on_conflict: false,
+            webhook_deletions: AtomicU32::new(0),
+            status: Mutex::new(UpdaterStatus::Starting),
+            poll_timeout: 30,
+            limit: 100,
+            allowed_updates: None,
+            offset: AtomicI64::new(0),
+            stopping: watch::Sender::new(false),
+            running: tokio::sync::Mutex::new(()),
         }
     }
 
-    pub fn set_client(&mut self, client: Client) {
-        self.client = client;
-    }
-
     pub fn set_url(&mut self, url: String) {
         self.url = url;
     }
@@ -43,45 +89,222 @@ impl LongPollUpdate {
         self.error_timeout_sleep = error_timeout_sleep;
     }
 
-    pub fn set_regex_token(&mut self, regex: Regex) {
-        self.token_regex = regex;
+    /// `getUpdates` parameters. `limit` is clamped to the 1..=100 range Telegram accepts.
+    pub fn set_poll_params(&mut self, poll_timeout: u64, limit: u64, allowed_updates: Option<Vec<String>>) {
+        self.poll_timeout = poll_timeout;
+        self.limit = limit.clamp(1, 100);
+        self.allowed_updates = allowed_updates;
+    }
+
+    fn query_params(&self, offset: i64) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("offset", offset.to_string()),
+            ("timeout", self.poll_timeout.to_string()),
+            ("limit", self.limit.to_string()),
+        ];
+        // Without the parameter Telegram keeps the filter of the previous call.
+        if let Some(allowed_updates) = &self.allowed_updates {
+            params.push(("allowed_updates", json!(allowed_updates).to_string()));
+        }
+        params
+    }
+
+    pub fn set_delete_webhook_on_conflict(&mut self, delete_webhook_on_conflict: bool) {
+        self.delete_webhook_on_conflict = delete_webhook_on_conflict;
+    }
+
+    fn set_status(&self, status: UpdaterStatus) {
+        *self.status.lock().expect("Updater status lock poisoned") = status;
+    }
+
+    fn status(&self) -> UpdaterStatus {
+        self.status.lock().expect("Updater status lock poisoned").clone()
+    }
+
+    /// Sleeps for `duration`, or less when polling is being stopped.
+    async fn pause(&self, duration: Duration) {
+        let mut stopping = self.stopping.subscribe();
+        tokio::select! {
+            _ = sleep(duration) => {}
+            _ = stopping.wait_for(|stopping| *stopping) => {}
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        *self.stopping.borrow()
+    }
+
+    /// Confirms every update tgin has taken over to Telegram, so the next poller,
+    /// with or without the offset file, does not get them again.
+    async fn confirm_offset(&self) {
+        let offset = self.offset.load(Ordering::Relaxed);
+        if offset == 0 {
+            return;
+        }
+        let params = [("offset", offset.to_string()), ("timeout", "0".to_string()), ("limit", "1".to_string())];
+        match self.client.get(&self.url).query(&params).send().await {
+            Ok(res) if res.status().is_success() => println!("Confirmed offset {} to Telegram", offset),
+            Ok(res) => eprintln!("Failed to confirm offset {}. Status: {}", offset, res.status()),
+            Err(err) => eprintln!("Network error confirming offset {}: {}", offset, err),
+        }
+    }
+
+    fn delete_webhook_url(&self) -> String {
+        match self.url.rsplit_once('/') {
+            Some((base, _)) => format!("{}/deleteWebhook", base),
+            None => self.url.clone(),
+        }
+    }
+
+    async fn delete_webhook(&self) {
+        match self.client.post(self.delete_webhook_url()).send().await {
+            Ok(res) if res.status().is_success() => println!("Deleted the active webhook to resume long polling"),
+            Ok(res) => eprintln!("Failed to delete webhook. Status: {}", res.status()),
+            Err(err) => eprintln!("Network error deleting webhook: {}", err),
+        }
+    }
+
+    /// Reacts to an `ok: false` response. Returns `false` when polling has to stop for good.
+    async fn handle_error(&self, error: ApiError) -> bool {
+        match error.code {
+            401 => {
+                eprintln!("Telegram rejected the bot token, long polling stopped: {}", error.description);
+                self.set_status(UpdaterStatus::Fatal(error.description));
+                return false;
+            }
+            429 => {
+                let retry_after = error.retry_after.map(Duration::from_secs)
+                    .unwrap_or(Duration::from_millis(self.error_timeout_sleep));
+                eprintln!("Telegram rate limit, retrying in {:?}: {}", retry_after, error.description);
+                self.set_status(UpdaterStatus::Retrying(format!("rate limited for {:?}", retry_after)));
+                self.pause(retry_after).await;
+            }
+            409 if self.delete_webhook_on_conflict
+                && self.webhook_deletions.load(Ordering::Relaxed) < MAX_WEBHOOK_DELETIONS =>
+            {
+                let attempt = self.webhook_deletions.fetch_add(1, Ordering::Relaxed) + 1;
+                eprintln!("Telegram reported a conflict: {}", error.description);
+                self.set_status(UpdaterStatus::Retrying(error.description));
+                self.delete_webhook().await;
+                if attempt == MAX_WEBHOOK_DELETIONS {
+                    eprintln!("Deleted the webhook {} times in a row, not deleting it again until polling succeeds", attempt);
+                }
+                self.pause(Duration::from_millis(self.error_timeout_sleep)).await;
+            }
+            code => {
+                eprintln!("Telegram API error {}: {}", code, error.description);
+                self.set_status(UpdaterStatus::Retrying(format!("{}: {}", code, error.description)));
+                self.pause(Duration::from_millis(self.error_timeout_sleep)).await;
+            }
+        }
+        true
+    }
+
+    pub fn set_offset_file(&mut self, offset_file: PathBuf) {
+        self.offset_file = Some(offset_file);
     }
 
 }
 
 #[async_trait]
 impl Updater for LongPollUpdate {
+    fn bot(&self) -> Option<String> {
+        Some(self.bot.clone())
+    }
+
+    async fn stop(&self) {
+        self.stopping.send_replace(true);
+        // Held by the polling loop until it is out of the way.
+        let _running = self.running.lock().await;
+        if !matches!(self.status(), UpdaterStatus::Fatal(_)) {
+            self.confirm_offset().await;
+            self.set_status(UpdaterStatus::Stopped);
+        }
+    }
+
     async fn start(&self, tx: Sender<Value>) {
+        let _running = self.running.lock().await;
+        let mut stopping = self.stopping.subscribe();
         let mut offset = 0;
 
-        loop {
-            let params = [("offset", offset.to_string()), ("timeout", "30".to_string()), ("limit", "100".to_string())];
-            match self.client.get(&self.url).query(&params).send().await {
+        let mut store = self.offset_file.clone().map(OffsetStore::new);
+        if let Some(store) = &mut store {
+            match store.load().await {
+                Ok(Some(stored)) => {
+                    offset = stored;
+                    self.offset.store(offset, Ordering::Relaxed);
+                }
+                Ok(None) => {}
+                Err(err) => eprintln!("Failed to load offset file, starting from the oldest update: {}", err),
+            }
+        }
+
+        while !self.is_stopping() {
+            let params = self.query_params(offset);
+            // Only the wait for new updates is cut short, updates already received are handed over.
+            let response = tokio::select! {
+                response = self.client.get(&self.url).query(&params).send() => response,
+                _ = stopping.wait_for(|stopping| *stopping) => break,
+            };
+            match response {
                 Ok(res) => {
+                    let status = res.status();
                     match res.json::<Value>().await {
+                        Ok(json) if json.get("ok").and_then(Value::as_bool) != Some(true) => {
+                            if !self.handle_error(ApiError::from_response(status, &json)).await {
+                                return;
+                            }
+                        }
                         Ok(json) => {
+                            self.set_status(UpdaterStatus::Running);
+                            self.webhook_deletions.store(0, Ordering::Relaxed);
+                            let mut handed_over = None;
+                            let mut closed = false;
                             if let Some(result) = json.get("result").and_then(|r| r.as_array()) {
                                 for update in result {
-                                    if let Some(id) = update.get("update_id").and_then(|i| i.as_i64()) {
-                                        offset = id + 1;
-                                        if tx.send(update.clone()).await.is_err() {
-                                            return;
+                                    if let Some(id) = update_id(update) {
+                                        // A full channel must not hold up stopping, the rest of the batch
+                                        // stays unconfirmed and Telegram hands it out again.
+                                        let sent = tokio::select! {
+                                            sent = tx.send(update.clone()) => sent.is_ok(),
+                                            _ = stopping.wait_for(|stopping| *stopping) => false,
+                                        };
+                                        if !sent {
+                                            closed = true;
+                                            break;
                                         }
+                                        handed_over = Some(id);
                                     }
                                 }
                             }
-                            sleep(Duration::from_millis(self.default_timeout_sleep)).await;
+                            // Committed once per batch, when its updates are in tgin's queue. They are not
+                            // routed yet: a crash loses the queued ones, a graceful shutdown routes them.
+                            if let Some(id) = handed_over {
+                                offset = id + 1;
+                                self.offset.store(offset, Ordering::Relaxed);
+                                if let Some(store) = &mut store {
+                                    if let Err(err) = store.commit(offset).await {
+                                        eprintln!("Failed to commit offset {}: {}", offset, err);
+                                    }
+                                }
+                            }
+                            if closed {
+                                return;
+                            }
+                            self.pause(Duration::from_millis(self.default_timeout_sleep)).await;
                         }
                         Err(err) => {
                             eprintln!("JSON parse error: {:?}", err);
-                            sleep(Duration::from_millis(self.error_timeout_sleep)).await;
+                            self.set_status(UpdaterStatus::Retrying(format!("{} with an invalid body", status)));
+                            self.pause(Duration::from_millis(self.error_timeout_sleep)).await;
                         }
                     }
                 }
                 Err(err) => {
                     eprintln!("Network error: {:?}", err);
+                    self.set_status(UpdaterStatus::Retrying(err.to_string()));
 
-                    sleep(Duration::from_millis(self.error_timeout_sleep)).await;
+                    self.pause(Duration::from_millis(self.error_timeout_sleep)).await;
                 }
             }
         }
@@ -100,6 +323,14 @@ impl Printable for LongPollUpdate {
 
         format!("longpull: {} {}", token, timeout_text)
     }
+
+    async fn json_struct(&self) -> Value {
+        json!({
+            "type": "longpoll",
+            "url": self.token_regex.replace_all(&self.url, "#####"),
+            "status": self.status().to_string()
+        })
+    }
 }
 
 
@@ -111,8 +342,9 @@ mod tests {
     use super::*;
     use wiremock::{MockServer, Mock, ResponseTemplate};
     use tokio::sync::mpsc;
-    use wiremock::matchers::{any, path}; 
+    use wiremock::matchers::{any, path, query_param}; 
     use tokio::time::timeout;
+    use std::sync::Arc;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_longpoll_fetches_updates_and_sends_to_channel() {
@@ -144,7 +376,7 @@ mod tests {
             .no_proxy()
             .build()
             .unwrap();
-        updater.set_client(client);
+        updater.client = client;
         
         let mock_url = format!("{}/botMYTOKEN/getUpdates", mock_server.uri());
         updater.set_url(mock_url);
@@ -174,4 +406,357 @@ mod tests {
     }
 
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_offset_is_resumed_and_committed() {
+        let offset_file = std::env::temp_dir().join(format!("tgin-updater-offset-{}", std::process::id()));
+        std::fs::write(&offset_file, "100").unwrap();
+
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(query_param("offset", "100"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "ok": true,
+                    "result": [
+                        { "update_id": 100, "message": { "text": "test1" } },
+                        { "update_id": 101, "message": { "text": "test2" } }
+                    ]
+                }))
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .and(query_param("offset", "102"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = LongPollUpdate::new("MYTOKEN".to_string());
+        updater.client = Client::builder().no_proxy().build().unwrap();
+        updater.set_url(format!("{}/botMYTOKEN/getUpdates", mock_server.uri()));
+        updater.set_timeouts(10, 10);
+        updater.set_offset_file(offset_file.clone());
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        for expected in [100, 101] {
+            let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+            assert_eq!(update["update_id"], expected);
+        }
+        sleep(Duration::from_millis(50)).await;
+        handle.abort();
+
+        assert_eq!(std::fs::read_to_string(&offset_file).unwrap(), "102");
+        std::fs::remove_file(&offset_file).unwrap();
+    }
+
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_offset_is_committed_after_the_whole_batch() {
+        let offset_file = std::env::temp_dir().join(format!("tgin-updater-batch-offset-{}", std::process::id()));
+        std::fs::write(&offset_file, "100").unwrap();
+
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .and(query_param("offset", "100"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true,
+                "result": [{ "update_id": 100 }, { "update_id": 101 }, { "update_id": 102 }]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .and(query_param("offset", "103"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = updater_for(&mock_server);
+        updater.set_timeouts(10, 10);
+        updater.set_offset_file(offset_file.clone());
+
+        // room for one update, the rest of the batch waits for tgin
+        let (tx, mut rx) = mpsc::channel(1);
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 100);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(std::fs::read_to_string(&offset_file).unwrap(), "100");
+
+        for expected in [101, 102] {
+            let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+            assert_eq!(update["update_id"], expected);
+        }
+        sleep(Duration::from_millis(50)).await;
+        handle.abort();
+
+        assert_eq!(std::fs::read_to_string(&offset_file).unwrap(), "103");
+        std::fs::remove_file(&offset_file).unwrap();
+    }
+
+    fn updater_for(mock_server: &MockServer) -> LongPollUpdate {
+        let mut updater = LongPollUpdate::new("MYTOKEN".to_string());
+        updater.client = Client::builder().no_proxy().build().unwrap();
+        updater.set_url(format!("{}/botMYTOKEN/getUpdates", mock_server.uri()));
+        updater.set_timeouts(0, 10);
+        updater
+    }
+
+    #[tokio::test]
+    async fn test_stop_interrupts_polling_and_confirms_offset() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(query_param("offset", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true, "result": [{ "update_id": 5, "message": { "text": "hi" } }]
+            })))
+            .mount(&mock_server)
+            .await;
+        // the long poll that is waiting for new updates when tgin stops
+        Mock::given(any())
+            .and(query_param("offset", "6"))
+            .and(query_param("timeout", "30"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"ok": true, "result": []}))
+                .set_delay(Duration::from_secs(30)))
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .and(query_param("offset", "6"))
+            .and(query_param("timeout", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let updater = Arc::new(updater_for(&mock_server));
+        let (tx, mut rx) = mpsc::channel(10);
+        let polling = updater.clone();
+        let handle = tokio::spawn(async move {
+            polling.start(tx).await;
+        });
+
+        let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 5);
+        sleep(Duration::from_millis(50)).await;
+
+        timeout(Duration::from_secs(2), updater.stop()).await.expect("stop did not interrupt the long poll");
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+        assert_eq!(updater.status(), UpdaterStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_stop_with_a_full_channel_confirms_only_sent_updates() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .and(query_param("offset", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true, "result": [{ "update_id": 5 }, { "update_id": 6 }, { "update_id": 7 }]
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .and(query_param("offset", "6"))
+            .and(query_param("timeout", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let updater = Arc::new(updater_for(&mock_server));
+        // room for one update and nobody reads it, the rest of the batch is stuck
+        let (tx, mut rx) = mpsc::channel(1);
+        let polling = updater.clone();
+        let handle = tokio::spawn(async move {
+            polling.start(tx).await;
+        });
+        sleep(Duration::from_millis(100)).await;
+
+        timeout(Duration::from_secs(1), updater.stop()).await.expect("stop waited for the full channel");
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+        assert_eq!(rx.recv().await.unwrap()["update_id"], 5);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_stops_polling() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "ok": false, "error_code": 401, "description": "Unauthorized"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let updater = updater_for(&mock_server);
+        let (tx, _rx) = mpsc::channel(10);
+
+        timeout(Duration::from_secs(1), updater.start(tx)).await.expect("polling did not stop");
+        assert_eq!(updater.status(), UpdaterStatus::Fatal("Unauthorized".to_string()));
+        assert_eq!(updater.json_struct().await["status"], "fatal: Unauthorized");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rate_limit_honors_retry_after() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).set_body_json(serde_json::json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests: retry after 1",
+                "parameters": { "retry_after": 1 }
+            })))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true, "result": [{ "update_id": 1 }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let updater = updater_for(&mock_server);
+        let (tx, mut rx) = mpsc::channel(10);
+        let started = std::time::Instant::now();
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        let update = timeout(Duration::from_secs(3), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 1);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        handle.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_conflict_deletes_webhook() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/botMYTOKEN/getUpdates"))
+            .respond_with(ResponseTemplate::new(409).set_body_json(serde_json::json!({
+                "ok": false,
+                "error_code": 409,
+                "description": "Conflict: can't use getUpdates method while webhook is active"
+            })))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/botMYTOKEN/deleteWebhook"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": true})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/botMYTOKEN/getUpdates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true, "result": [{ "update_id": 5 }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = updater_for(&mock_server);
+        updater.set_delete_webhook_on_conflict(true);
+        let (tx, mut rx) = mpsc::channel(10);
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 5);
+        handle.abort();
+    }
+
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_conflict_backs_off_and_caps_webhook_deletions() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/botMYTOKEN/getUpdates"))
+            .respond_with(ResponseTemplate::new(409).set_body_json(serde_json::json!({
+                "ok": false,
+                "error_code": 409,
+                "description": "Conflict: can't use getUpdates method while webhook is active"
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/botMYTOKEN/deleteWebhook"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true, "result": true})))
+            .expect(MAX_WEBHOOK_DELETIONS as u64)
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = updater_for(&mock_server);
+        updater.set_timeouts(0, 50);
+        updater.set_delete_webhook_on_conflict(true);
+        let (tx, _rx) = mpsc::channel(10);
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        sleep(Duration::from_millis(400)).await;
+        handle.abort();
+
+        // every conflict waits out the error timeout, so only a handful of polls were made
+        let requests = mock_server.received_requests().await.unwrap();
+        let polls = requests.iter().filter(|request| request.url.path().ends_with("/getUpdates")).count();
+        assert!(polls > MAX_WEBHOOK_DELETIONS as usize && polls <= 9, "{} polls", polls);
+    }
+
+    #[test]
+    fn test_query_params() {
+        let mut updater = LongPollUpdate::new("MYTOKEN".to_string());
+        assert_eq!(updater.query_params(7), vec![
+            ("offset", "7".to_string()),
+            ("timeout", "30".to_string()),
+            ("limit", "100".to_string()),
+        ]);
+
+        updater.set_poll_params(50, 500, Some(vec!["message".to_string(), "chat_member".to_string()]));
+        assert_eq!(updater.query_params(0), vec![
+            ("offset", "0".to_string()),
+            ("timeout", "50".to_string()),
+            ("limit", "100".to_string()),
+            ("allowed_updates", r#"["message","chat_member"]"#.to_string()),
+        ]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_poll_params_are_sent() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(query_param("timeout", "5"))
+            .and(query_param("limit", "10"))
+            .and(query_param("allowed_updates", r#"["message_reaction"]"#))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ok": true, "result": [{ "update_id": 1 }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = updater_for(&mock_server);
+        updater.set_poll_params(5, 10, Some(vec!["message_reaction".to_string()]));
+        let (tx, mut rx) = mpsc::channel(10);
+        let handle = tokio::spawn(async move {
+            updater.start(tx).await;
+        });
+
+        let update = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(update["update_id"], 1);
+        handle.abort();
+    }
+
+
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+use std::io;
+use std::path::PathBuf;
+
+use tokio::fs;
+
+
+/// Last committed `getUpdates` offset kept in a small state file, so polling resumes
+/// where it stopped after a restart.
+pub struct OffsetStore {
+    path: PathBuf,
+    committed: Option<i64>,
+}
+
+impl OffsetStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, committed: None }
+    }
+
+    /// Reads the stored offset. A missing file means nothing was committed yet.
+    pub async fn load(&mut self) -> io::Result<Option<i64>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => {
+                let offset = content
+                    .trim()
+                    .parse()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                self.committed = Some(offset);
+                Ok(Some(offset))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Stores the offset through a temporary file and a rename, so a crash never leaves a torn file.
+    pub async fn commit(&mut self, offset: i64) -> io::Result<()> {
+        if self.committed == Some(offset) {
+            return Ok(());
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, offset.to_string()).await?;
+        fs::rename(&tmp, &self.path).await?;
+
+        self.committed = Some(offset);
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_commit_and_load() {
+        let path = std::env::temp_dir().join(format!("tgin-offset-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = OffsetStore::new(path.clone());
+        assert_eq!(store.load().await.unwrap(), None);
+
+        store.commit(42).await.unwrap();
+        store.commit(43).await.unwrap();
+
+        let mut reloaded = OffsetStore::new(path.clone());
+        assert_eq!(reloaded.load().await.unwrap(), Some(43));
+
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(reloaded.load().await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,25 +1,49 @@
 use crate::base::{Serverable, Printable};
-use crate::update::base::Updater;
+use crate::update::base::{Updater, bot_id};
+use crate::update::allowlist::IpAllowlist;
+use crate::utils::update::validate;
 
 use crate::utils::defaults::TELEGRAM_TOKEN_REGEX;
 
 use async_trait::async_trait;
-use axum::{extract::State, routing::post, Json, Router};
+use axum::{extract::{ConnectInfo, State}, http::{header::CONTENT_LENGTH, HeaderMap, StatusCode}, routing::post, Router};
+use axum::body::{to_bytes, Body};
 use serde_json::{json, Value};
 
 use reqwest::Client;
+use reqwest::multipart::{Form, Part};
 
 use tokio::sync::mpsc::Sender;
+use tokio::time::timeout;
 
 use regex::Regex;
 
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct RegistrationWebhookConfig {
     public_ip: String,
     client: Client,
     set_webhook_url: String,
+    bot: String,
 
     token_regex: Regex,
 
+    pub secret_token: Option<String>,
+    pub allowed_updates: Option<Vec<String>>,
+    pub max_connections: Option<u32>,
+    pub drop_pending_updates: bool,
+    pub ip_address: Option<String>,
+    pub certificate: Option<PathBuf>,
+    pub delete_on_shutdown: bool,
 }
 
 impl RegistrationWebhookConfig {
@@ -28,55 +52,160 @@ impl RegistrationWebhookConfig {
             public_ip,
             client: Client::new(),
             set_webhook_url: format!("https://api.telegram.org/bot{}/setWebhook", token),
+            bot: bot_id(&token),
             token_regex: Regex::new(TELEGRAM_TOKEN_REGEX).unwrap(),
+            secret_token: None,
+            allowed_updates: None,
+            max_connections: None,
+            drop_pending_updates: false,
+            ip_address: None,
+            certificate: None,
+            delete_on_shutdown: false,
         }
     }
 
-    pub fn set_client(&mut self, client: Client) {
-        self.client = client;
-    }
-
     pub fn set_webhook_url(&mut self, set_webhook_url: String) {
         self.set_webhook_url = set_webhook_url;
     }
 
-    pub fn set_regex_token(&mut self, regex: Regex) {
-        self.token_regex = regex;
+    fn delete_webhook_url(&self) -> String {
+        match self.set_webhook_url.rsplit_once('/') {
+            Some((base, _)) => format!("{}/deleteWebhook", base),
+            None => self.set_webhook_url.clone(),
+        }
     }
 
+    /// Text fields of the `setWebhook` call, in the form Telegram expects them.
+    fn params(&self, url: String) -> Vec<(&'static str, String)> {
+        let mut params = vec![("url", url)];
+        if let Some(secret_token) = &self.secret_token {
+            params.push(("secret_token", secret_token.clone()));
+        }
+        if let Some(allowed_updates) = &self.allowed_updates {
+            params.push(("allowed_updates", json!(allowed_updates).to_string()));
+        }
+        if let Some(max_connections) = self.max_connections {
+            params.push(("max_connections", max_connections.to_string()));
+        }
+        if self.drop_pending_updates {
+            params.push(("drop_pending_updates", "true".to_string()));
+        }
+        if let Some(ip_address) = &self.ip_address {
+            params.push(("ip_address", ip_address.clone()));
+        }
+        params
+    }
 }
 
 
 pub struct WebhookUpdate {
     path: String,
     registration: Option<RegistrationWebhookConfig>, 
+    secret_token: Option<String>,
+    allowlist: Option<Arc<IpAllowlist>>,
+    max_body_size: usize,
+    read_timeout: Duration,
+    rejected: Arc<AtomicU64>,
+    invalid: Arc<AtomicU64>,
+    bot: Option<String>,
+    sink: Arc<OnceLock<Sender<Value>>>,
+    closed: Arc<AtomicBool>,
 }
 
 
 
 impl WebhookUpdate {
     pub fn new(path: String) -> Self {
-        Self { path, registration: None }
+        Self {
+            path,
+            registration: None,
+            secret_token: None,
+            allowlist: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            rejected: Arc::new(AtomicU64::new(0)),
+            invalid: Arc::new(AtomicU64::new(0)),
+            bot: None,
+            sink: Arc::new(OnceLock::new()),
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_secret_token(&mut self, secret_token: String) {
+        self.secret_token = Some(secret_token);
+    }
+
+    pub fn set_allowlist(&mut self, allowlist: IpAllowlist) {
+        self.allowlist = Some(Arc::new(allowlist));
+    }
+
+    pub fn set_limits(&mut self, max_body_size: usize, read_timeout: Duration) {
+        self.max_body_size = max_body_size;
+        self.read_timeout = read_timeout;
+    }
+
+    pub fn set_bot(&mut self, bot: String) {
+        self.bot = Some(bot);
+    }
+
+    /// The token incoming requests must carry: the configured one, or the one sent to Telegram on registration.
+    fn expected_secret(&self) -> Option<String> {
+        self.secret_token.clone()
+            .or_else(|| self.registration.as_ref().and_then(|reg| reg.secret_token.clone()))
+    }
+
+    pub fn set_registration(&mut self, registration: RegistrationWebhookConfig) {
+        self.registration = Some(registration);
     }
 
 
     pub async fn register_webhook(&self, config: &RegistrationWebhookConfig) {
         let full_url = format!("{}{}", config.public_ip.trim_end_matches('/'), self.path);
+        let params = config.params(full_url);
+
+        let request = config.client.post(&config.set_webhook_url);
+        let request = match &config.certificate {
+            // A self-signed certificate has to be uploaded as a file, which needs multipart/form-data.
+            Some(certificate) => {
+                let content = match tokio::fs::read(certificate).await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        eprintln!("Failed to read webhook certificate {}: {}", certificate.display(), e);
+                        return;
+                    }
+                };
+
+                let form = params.into_iter()
+                    .fold(Form::new(), |form, (name, value)| form.text(name, value))
+                    .part("certificate", Part::bytes(content).file_name("certificate.pem"));
+
+                request.multipart(form)
+            }
+            None => request.form(&params),
+        };
 
-        let params = json!({ "url": full_url });
-
-        match config.client.post(&config.set_webhook_url).json(&params).send().await {
+        match request.send().await {
             Ok(resp) => {
-                if resp.status().is_success() {
+                let status = resp.status();
+                let body = resp.json::<Value>().await.unwrap_or(Value::Null);
+                if status.is_success() && body["ok"] != json!(false) {
                     println!("Webhook set successfully for path: {}", self.path);
                 } else {
-                    eprintln!("Failed to set webhook. Status: {}", resp.status());
+                    eprintln!("Failed to set webhook. Status: {} {}", status, body["description"].as_str().unwrap_or(""));
                 }
             }
             Err(e) => eprintln!("Network error setting webhook: {}", e),
         }
     }
 
+    pub async fn delete_webhook(&self, config: &RegistrationWebhookConfig) {
+        match config.client.post(config.delete_webhook_url()).send().await {
+            Ok(resp) if resp.status().is_success() => println!("Webhook deleted for path: {}", self.path),
+            Ok(resp) => eprintln!("Failed to delete webhook. Status: {}", resp.status()),
+            Err(e) => eprintln!("Network error deleting webhook: {}", e),
+        }
+    }
+
 
 }
 
@@ -89,19 +218,135 @@ impl Updater for WebhookUpdate {
             println!("Webhook started in passive mode (no auto-registration) for {}", self.path);
         }
     }
+
+    // The handler sends into the channel tgin gave this provider instead of the shared one
+    // from the router state, which is only used when the provider runs without tgin.
+    fn attach(&self, tx: Sender<Value>) {
+        let _ = self.sink.set(tx);
+    }
+
+    async fn stop(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(config) = self.registration.as_ref().filter(|config| config.delete_on_shutdown) {
+            self.delete_webhook(config).await;
+        }
+    }
+
+    fn bot(&self) -> Option<String> {
+        self.bot.clone().or_else(|| self.registration.as_ref().map(|reg| reg.bot.clone()))
+    }
 }
 
 
 #[async_trait]
 impl Serverable for WebhookUpdate {
     async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let ingress = Arc::new(Ingress {
+            secret: self.expected_secret(),
+            allowlist: self.allowlist.clone(),
+            max_body_size: self.max_body_size,
+            read_timeout: self.read_timeout,
+            rejected: self.rejected.clone(),
+            invalid: self.invalid.clone(),
+            sink: self.sink.clone(),
+            closed: self.closed.clone(),
+        });
+
+        let handler = move |State(tx): State<Sender<Value>>,
+                            peer: Option<ConnectInfo<SocketAddr>>,
+                            headers: HeaderMap,
+                            body: Body| {
+            let ingress = ingress.clone();
+
+            async move {
+                ingress.handle(tx, peer.map(|ConnectInfo(peer)| peer), headers, body).await
+            }
+        };
+
         router.route(&self.path, post(handler))
     }
 }
 
 
-async fn handler(State(tx): State<Sender<Value>>, Json(update): Json<Value>) {
-    let _ = tx.send(update).await;
+struct Ingress {
+    secret: Option<String>,
+    allowlist: Option<Arc<IpAllowlist>>,
+    max_body_size: usize,
+    read_timeout: Duration,
+    rejected: Arc<AtomicU64>,
+    invalid: Arc<AtomicU64>,
+    sink: Arc<OnceLock<Sender<Value>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Ingress {
+    async fn handle(&self, tx: Sender<Value>, peer: Option<SocketAddr>, headers: HeaderMap, body: Body) -> StatusCode {
+        // Telegram retries the update later, by then another instance takes it.
+        if self.closed.load(Ordering::Relaxed) {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        if let Some(allowlist) = &self.allowlist {
+            // Without a peer address there is nothing to check, so the request is refused.
+            let allowed = peer.is_some_and(|peer| allowlist.allows(allowlist.client_ip(peer.ip(), &headers)));
+            if !allowed {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return StatusCode::FORBIDDEN;
+            }
+        }
+        if let Some(secret) = &self.secret {
+            let provided = headers.get(SECRET_TOKEN_HEADER).map(|value| value.as_bytes());
+            if !provided.is_some_and(|provided| constant_time_eq(provided, secret.as_bytes())) {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return StatusCode::UNAUTHORIZED;
+            }
+        }
+
+        let declared = headers.get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if declared.is_some_and(|len| len > self.max_body_size) {
+            self.invalid.fetch_add(1, Ordering::Relaxed);
+            return StatusCode::PAYLOAD_TOO_LARGE;
+        }
+
+        let bytes = match timeout(self.read_timeout, to_bytes(body, self.max_body_size)).await {
+            Ok(Ok(bytes)) => bytes,
+            // Either the body grew past the limit or the client went away, in which case
+            // nobody reads the answer anyway.
+            Ok(Err(_)) => {
+                self.invalid.fetch_add(1, Ordering::Relaxed);
+                return StatusCode::PAYLOAD_TOO_LARGE;
+            }
+            Err(_) => {
+                self.invalid.fetch_add(1, Ordering::Relaxed);
+                return StatusCode::REQUEST_TIMEOUT;
+            }
+        };
+
+        let update = match serde_json::from_slice::<Value>(&bytes).map_err(|e| e.to_string()).and_then(|update| {
+            validate(&update)?;
+            Ok(update)
+        }) {
+            Ok(update) => update,
+            Err(reason) => {
+                self.invalid.fetch_add(1, Ordering::Relaxed);
+                eprintln!("Dropping invalid webhook payload: {}", reason);
+                return StatusCode::BAD_REQUEST;
+            }
+        };
+
+        // A closed channel means tgin is going down, Telegram sends the update again later.
+        let tx = self.sink.get().unwrap_or(&tx);
+        match tx.send(update).await {
+            Ok(()) => StatusCode::OK,
+            Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 
@@ -114,6 +359,18 @@ impl Printable for WebhookUpdate {
         };
         format!("webhook: 0.0.0.0{} {}", self.path, reg_text)
     }
+
+    async fn json_struct(&self) -> Value {
+        json!({
+            "type": "webhook",
+            "path": self.path,
+            "registration": self.registration.is_some(),
+            "secret_token": self.expected_secret().is_some(),
+            "allowlist": self.allowlist.is_some(),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "invalid": self.invalid.load(Ordering::Relaxed)
+        })
+    }
 }
 
 
@@ -123,11 +380,13 @@ impl Printable for WebhookUpdate {
 mod tests {
     use super::*;
     use wiremock::{MockServer, Mock, ResponseTemplate};
-    use wiremock::matchers::method;
+    use wiremock::matchers::{method, path, header_regex, body_string_contains};
     use tokio::sync::mpsc;
     use tower::ServiceExt;
     use axum::body::Body;
-    use axum::http::{Request, StatusCode};
+    use axum::http::Request;
+    use axum::extract::connect_info::MockConnectInfo;
+    use crate::update::allowlist::{parse_ranges, TrustedProxy};
 
 
     #[tokio::test]
@@ -152,7 +411,7 @@ mod tests {
             .no_proxy()
             .build()
             .unwrap();
-        reg_config.set_client(client);
+        reg_config.client = client;
 
         reg_config.set_webhook_url(format!("{}/setWebhook", mock_server.uri()));
 
@@ -164,6 +423,95 @@ mod tests {
         updater.start(tx).await;
     }
 
+    fn registration_for(server: &MockServer) -> RegistrationWebhookConfig {
+        let mut reg_config = RegistrationWebhookConfig::new("TOKEN123".to_string(), "https://my-server.com/".to_string());
+        reg_config.client = Client::builder().no_proxy().build().unwrap();
+        reg_config.set_webhook_url(format!("{}/botTOKEN123/setWebhook", server.uri()));
+        reg_config
+    }
+
+    #[tokio::test]
+    async fn test_webhook_registration_sends_all_options() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/botTOKEN123/setWebhook"))
+            .and(header_regex("content-type", "application/x-www-form-urlencoded"))
+            .and(body_string_contains("url=https%3A%2F%2Fmy-server.com%2Fwebhook"))
+            .and(body_string_contains("secret_token=s3cret"))
+            .and(body_string_contains("allowed_updates=%5B%22message%22%2C%22callback_query%22%5D"))
+            .and(body_string_contains("max_connections=40"))
+            .and(body_string_contains("drop_pending_updates=true"))
+            .and(body_string_contains("ip_address=1.2.3.4"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": true})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut reg_config = registration_for(&mock_server);
+        reg_config.secret_token = Some("s3cret".to_string());
+        reg_config.allowed_updates = Some(vec!["message".to_string(), "callback_query".to_string()]);
+        reg_config.max_connections = Some(40);
+        reg_config.drop_pending_updates = true;
+        reg_config.ip_address = Some("1.2.3.4".to_string());
+
+        let mut updater = WebhookUpdate::new("/webhook".to_string());
+        updater.set_registration(reg_config);
+
+        let (tx, _) = mpsc::channel(1);
+        updater.start(tx).await;
+    }
+
+    #[tokio::test]
+    async fn test_webhook_registration_uploads_certificate() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/botTOKEN123/setWebhook"))
+            .and(header_regex("content-type", "^multipart/form-data; boundary="))
+            .and(body_string_contains("name=\"url\"\r\n\r\nhttps://my-server.com/webhook"))
+            .and(body_string_contains("filename=\"certificate.pem\""))
+            .and(body_string_contains("-----BEGIN CERTIFICATE-----"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": true})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let certificate = std::env::temp_dir().join(format!("tgin-cert-{}.pem", std::process::id()));
+        tokio::fs::write(&certificate, "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n").await.unwrap();
+
+        let mut reg_config = registration_for(&mock_server);
+        reg_config.certificate = Some(certificate.clone());
+
+        let mut updater = WebhookUpdate::new("/webhook".to_string());
+        updater.set_registration(reg_config);
+
+        let (tx, _) = mpsc::channel(1);
+        updater.start(tx).await;
+
+        tokio::fs::remove_file(certificate).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop_deletes_webhook_when_configured() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/botTOKEN123/deleteWebhook"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": true})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut updater = WebhookUpdate::new("/webhook".to_string());
+        updater.set_registration(registration_for(&mock_server));
+        // delete_on_shutdown is off, nothing is sent
+        updater.stop().await;
+
+        updater.registration.as_mut().unwrap().delete_on_shutdown = true;
+        updater.stop().await;
+    }
+
     #[tokio::test]
     async fn test_webhook_handler_receives_json_and_sends_to_channel() {
         let updater = WebhookUpdate::new("/bot/update".to_string());
@@ -194,4 +542,196 @@ mod tests {
         assert_eq!(received["update_id"], 999);
         assert_eq!(received["message"]["text"], "Hello via Webhook");
     }
+
+    fn request_with_secret(secret: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("/bot/update")
+            .header("content-type", "application/json");
+        if let Some(secret) = secret {
+            request = request.header("X-Telegram-Bot-Api-Secret-Token", secret);
+        }
+        request.body(Body::from(json!({"update_id": 1, "message": {"text": "hi"}}).to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wrong_secret_token_is_rejected() {
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_secret_token("s3cret".to_string());
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let response = app.clone().oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(request_with_secret(Some("wrong"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(updater.json_struct().await["rejected"], 2);
+
+        let response = app.oneshot(request_with_secret(Some("s3cret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.recv().await.unwrap()["update_id"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_attached_channel_takes_updates() {
+        let updater = WebhookUpdate::new("/bot/update".to_string());
+        let (attached_tx, mut attached) = mpsc::channel(10);
+        updater.attach(attached_tx);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let response = app.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(attached.recv().await.unwrap()["update_id"], 1);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_rejects_unknown_sources() {
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_allowlist(IpAllowlist::new(parse_ranges(&["telegram".to_string()]).unwrap()));
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let outsider = app.clone().layer(MockConnectInfo(SocketAddr::from(([1, 2, 3, 4], 443))));
+        let response = outsider.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(rx.try_recv().is_err());
+
+        // no connection info at all
+        let response = app.clone().oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(updater.json_struct().await["rejected"], 2);
+
+        let telegram = app.layer(MockConnectInfo(SocketAddr::from(([149, 154, 167, 220], 443))));
+        let response = telegram.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.recv().await.unwrap()["update_id"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_behind_trusted_proxy() {
+        let mut allowlist = IpAllowlist::new(parse_ranges(&["telegram".to_string()]).unwrap());
+        allowlist.set_trusted_proxy(TrustedProxy {
+            header: "x-real-ip".to_string(),
+            proxies: vec!["127.0.0.1".parse().unwrap()],
+        });
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_allowlist(allowlist);
+
+        let (tx, _rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx)
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+
+        let mut request = request_with_secret(None);
+        request.headers_mut().insert("x-real-ip", "91.108.4.10".parse().unwrap());
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut request = request_with_secret(None);
+        request.headers_mut().insert("x-real-ip", "8.8.8.8".parse().unwrap());
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    fn raw_request(body: Body) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/bot/update")
+            .header("content-type", "application/json")
+            .body(body)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_payloads_are_rejected() {
+        let updater = WebhookUpdate::new("/bot/update".to_string());
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        for body in ["not json", "[1, 2]", r#"{"message": {}}"#, r#"{"update_id": "7", "message": {}}"#, r#"{"update_id": 7, "garbage": 1}"#] {
+            let response = app.clone().oneshot(raw_request(Body::from(body))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+        assert!(rx.try_recv().is_err());
+        assert_eq!(updater.json_struct().await["invalid"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_body_size_limit() {
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_limits(64, DEFAULT_READ_TIMEOUT);
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let big = json!({"update_id": 1, "message": {"text": "x".repeat(100)}}).to_string();
+        let response = app.clone().oneshot(raw_request(Body::from(big.clone()))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // streamed without a content-length
+        let stream = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(big)]);
+        let response = app.clone().oneshot(raw_request(Body::from_stream(stream))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = app.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.recv().await.unwrap()["update_id"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_body_times_out() {
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_limits(DEFAULT_MAX_BODY_SIZE, Duration::from_millis(50));
+        let (tx, _rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let stream = futures_util::stream::pending::<Result<String, std::io::Error>>();
+        let response = app.oneshot(raw_request(Body::from_stream(stream))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_stopped_webhook_refuses_updates() {
+        let updater = WebhookUpdate::new("/bot/update".to_string());
+        let (tx, mut rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        updater.stop().await;
+
+        let response = app.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_closed_channel_answers_service_unavailable() {
+        let updater = WebhookUpdate::new("/bot/update".to_string());
+        let (tx, rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+        drop(rx);
+
+        let response = app.oneshot(request_with_secret(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_registration_secret_token_is_verified() {
+        let mut reg_config = RegistrationWebhookConfig::new("TOKEN123".to_string(), "https://my-server.com".to_string());
+        reg_config.secret_token = Some("from-registration".to_string());
+
+        let mut updater = WebhookUpdate::new("/bot/update".to_string());
+        updater.set_registration(reg_config);
+
+        let (tx, _rx) = mpsc::channel(10);
+        let app = updater.set_server(Router::new()).await.with_state(tx);
+
+        let response = app.clone().oneshot(request_with_secret(Some("wrong"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.oneshot(request_with_secret(Some("from-registration"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+
+/// An IPv4 or IPv6 network such as `149.154.160.0/20`. A bare address is a network of one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| format!("invalid address in '{}'", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let net: Cidr = "149.154.160.0/20".parse().unwrap();
+        assert!(net.contains(ip("149.154.160.1")));
+        assert!(net.contains(ip("149.154.175.255")));
+        assert!(!net.contains(ip("149.154.176.0")));
+        assert!(net.contains(ip("::ffff:149.154.167.99")));
+
+        let single: Cidr = "10.0.0.1".parse().unwrap();
+        assert!(single.contains(ip("10.0.0.1")));
+        assert!(!single.contains(ip("10.0.0.2")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("8.8.8.8")));
+        assert!(!any.contains(ip("2001:db8::1")));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(ip("2001:db8:1::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/x".parse::<Cidr>().is_err());
+        assert_eq!("10.0.0.0/8".parse::<Cidr>().unwrap().to_string(), "10.0.0.0/8");
+    }
+}
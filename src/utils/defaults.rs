@@ -4,3 +4,35 @@
 
 
 pub const TELEGRAM_TOKEN_REGEX: &str = r"(\d{8,15}):([a-zA-Z0-9_-]{30,50})";
+
+pub const TELEGRAM_UPDATE_TYPES: &[&str] = &[
+    "message",
+    "edited_message",
+    "channel_post",
+    "edited_channel_post",
+    "business_connection",
+    "business_message",
+    "edited_business_message",
+    "deleted_business_messages",
+    "message_reaction",
+    "message_reaction_count",
+    "inline_query",
+    "chosen_inline_result",
+    "callback_query",
+    "shipping_query",
+    "pre_checkout_query",
+    "purchased_paid_media",
+    "poll",
+    "poll_answer",
+    "my_chat_member",
+    "chat_member",
+    "chat_join_request",
+    "chat_boost",
+    "removed_chat_boost",
+];
+
+/// Networks Telegram sends webhook requests from, https://core.telegram.org/bots/webhooks
+pub const TELEGRAM_WEBHOOK_RANGES: &[&str] = &[
+    "149.154.160.0/20",
+    "91.108.4.0/22",
+];
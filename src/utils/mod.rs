@@ -1 +1,3 @@
-pub mod defaults;
\ No newline at end of file
+pub mod defaults;
+pub mod update;
+pub mod cidr;
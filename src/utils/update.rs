@@ -0,0 +1,146 @@
+use serde_json::Value;
+
+use crate::utils::defaults::TELEGRAM_UPDATE_TYPES;
+
+
+pub enum UpdateKey {
+    Chat,
+    User,
+    Path(Vec<String>),
+}
+
+impl UpdateKey {
+    pub fn path(path: &str) -> Self {
+        UpdateKey::Path(path.split('.').map(|s| s.to_string()).collect())
+    }
+
+    pub fn extract<'a>(&self, update: &'a Value) -> Option<&'a Value> {
+        match self {
+            UpdateKey::Chat => chat_id(update),
+            UpdateKey::User => user_id(update),
+            UpdateKey::Path(segments) => json_path(update, segments),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            UpdateKey::Chat => "chat".to_string(),
+            UpdateKey::User => "user".to_string(),
+            UpdateKey::Path(segments) => segments.join("."),
+        }
+    }
+}
+
+
+fn payload(update: &Value) -> Option<&Value> {
+    update_type(update).and_then(|kind| update.get(kind))
+}
+
+pub fn update_id(update: &Value) -> Option<i64> {
+    update.get("update_id").and_then(Value::as_i64)
+}
+
+pub fn chat_id(update: &Value) -> Option<&Value> {
+    let payload = payload(update)?;
+
+    payload.pointer("/chat/id")
+        .or_else(|| payload.pointer("/message/chat/id"))
+        .or_else(|| user_id(update))
+}
+
+pub fn user_id(update: &Value) -> Option<&Value> {
+    let payload = payload(update)?;
+
+    payload.pointer("/from/id")
+        .or_else(|| payload.pointer("/user/id"))
+}
+
+pub fn json_path<'a>(update: &'a Value, segments: &[String]) -> Option<&'a Value> {
+    segments.iter().try_fold(update, |value, segment| match value {
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => value.get(segment),
+    })
+    .filter(|value| !value.is_null())
+}
+
+
+pub fn update_type(update: &Value) -> Option<&str> {
+    let fields = update.as_object()?;
+
+    TELEGRAM_UPDATE_TYPES
+        .iter()
+        .find(|kind| fields.contains_key(**kind))
+        .copied()
+        .or_else(|| fields.keys().map(|k| k.as_str()).find(|k| *k != "update_id"))
+}
+
+/// Checks that `update` looks like a Telegram Update: an object with an integer `update_id`
+/// and at least one known update field holding an object.
+pub fn validate(update: &Value) -> Result<(), String> {
+    let fields = update.as_object().ok_or("update is not a JSON object")?;
+
+    if !fields.get("update_id").is_some_and(|id| id.is_i64() || id.is_u64()) {
+        return Err("update_id is missing or not an integer".to_string());
+    }
+    if !TELEGRAM_UPDATE_TYPES.iter().any(|kind| fields.get(*kind).is_some_and(Value::is_object)) {
+        return Err("update carries none of the known update fields".to_string());
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_update_type_known_kinds() {
+        assert_eq!(update_type(&json!({"update_id": 1, "message": {}})), Some("message"));
+        assert_eq!(update_type(&json!({"update_id": 2, "inline_query": {}})), Some("inline_query"));
+        assert_eq!(update_type(&json!({"update_id": 3, "my_chat_member": {}})), Some("my_chat_member"));
+    }
+
+    #[test]
+    fn test_chat_and_user_keys() {
+        let message = json!({"update_id": 1, "message": {"chat": {"id": -100}, "from": {"id": 7}}});
+        let callback = json!({"update_id": 2, "callback_query": {"from": {"id": 8}, "message": {"chat": {"id": -200}}}});
+        let inline = json!({"update_id": 3, "inline_query": {"from": {"id": 9}, "query": ""}});
+
+        assert_eq!(UpdateKey::Chat.extract(&message), Some(&json!(-100)));
+        assert_eq!(UpdateKey::User.extract(&message), Some(&json!(7)));
+        assert_eq!(UpdateKey::Chat.extract(&callback), Some(&json!(-200)));
+        assert_eq!(UpdateKey::Chat.extract(&inline), Some(&json!(9)));
+        assert_eq!(UpdateKey::Chat.extract(&json!({"update_id": 4, "poll": {"id": "x"}})), None);
+    }
+
+    #[test]
+    fn test_json_path_key() {
+        let update = json!({"update_id": 1, "message": {"entities": [{"type": "bot_command"}]}});
+
+        assert_eq!(UpdateKey::path("message.entities.0.type").extract(&update), Some(&json!("bot_command")));
+        assert_eq!(UpdateKey::path("message.entities.5.type").extract(&update), None);
+        assert_eq!(UpdateKey::path("update_id").extract(&update), Some(&json!(1)));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&json!({"update_id": 1, "message": {"text": "hi"}})).is_ok());
+        assert!(validate(&json!({"update_id": 1, "chat_boost": {}})).is_ok());
+
+        assert!(validate(&json!([1, 2])).is_err());
+        assert!(validate(&json!({"message": {}})).is_err());
+        assert!(validate(&json!({"update_id": "1", "message": {}})).is_err());
+        assert!(validate(&json!({"update_id": 1.5, "message": {}})).is_err());
+        assert!(validate(&json!({"update_id": 1})).is_err());
+        assert!(validate(&json!({"update_id": 1, "message": "hi"})).is_err());
+        assert!(validate(&json!({"update_id": 1, "something": {}})).is_err());
+    }
+
+    #[test]
+    fn test_update_type_unknown_and_invalid() {
+        assert_eq!(update_type(&json!({"update_id": 1, "future_kind": {}})), Some("future_kind"));
+        assert_eq!(update_type(&json!({"update_id": 1})), None);
+        assert_eq!(update_type(&json!("text")), None);
+    }
+}